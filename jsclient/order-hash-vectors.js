@@ -0,0 +1,73 @@
+const Sdk = require('@1inch/cross-chain-sdk');
+const {parseUnits, parseEther, getAddress} = require('ethers');
+
+// Prints the Limit Order Protocol fields and EIP-712 order hashes of the order built in
+// order-creation.js, with its random inputs pinned, for the test vectors of the
+// Stellar source factory (stellar-contracts/contracts/escrow-src-factory/src/eip712.rs)
+//
+// Run with `npm ci && npm run order-hash-vectors`; package-lock.json pins the SDK version
+(async () => {
+    const srcChainId = 1
+    const dstChainId = 56
+
+    const order = Sdk.CrossChainOrder.new(
+        new Sdk.Address(getAddress('0x1111111111111111111111111111111111111111')),
+        {
+            salt: 500n,
+            maker: new Sdk.Address(getAddress('0x2222222222222222222222222222222222222222')),
+            makingAmount: parseUnits('100', 6),
+            takingAmount: parseUnits('99', 6),
+            makerAsset: new Sdk.Address(getAddress('0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48')),
+            takerAsset: new Sdk.Address(getAddress('0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d'))
+        },
+        {
+            hashLock: Sdk.HashLock.forSingleFill('0x' + '02'.repeat(32)),
+            timeLocks: Sdk.TimeLocks.new({
+                srcWithdrawal: 10n,
+                srcPublicWithdrawal: 120n,
+                srcCancellation: 121n,
+                srcPublicCancellation: 122n,
+                dstWithdrawal: 10n,
+                dstPublicWithdrawal: 100n,
+                dstCancellation: 101n
+            }),
+            srcChainId,
+            dstChainId,
+            srcSafetyDeposit: parseEther('0.001'),
+            dstSafetyDeposit: parseEther('0.001')
+        },
+        {
+            auction: new Sdk.AuctionDetails({
+                initialRateBump: 0,
+                points: [],
+                duration: 120n,
+                startTime: 0n
+            }),
+            whitelist: [
+                {
+                    address: new Sdk.Address(getAddress('0x4444444444444444444444444444444444444444')),
+                    allowFrom: 0n
+                }
+            ],
+            resolvingStartTime: 0n
+        },
+        {
+            nonce: 0x6502b1a4c7n,
+            allowPartialFills: false,
+            allowMultipleFills: false
+        }
+    )
+
+    const toHex32 = (value) => '0x' + BigInt(value).toString(16).padStart(64, '0')
+    const built = order.build()
+    console.log('salt:', toHex32(built.salt))
+    console.log('maker:', built.maker)
+    console.log('receiver:', built.receiver)
+    console.log('makerAsset:', built.makerAsset)
+    console.log('takerAsset:', built.takerAsset)
+    console.log('makingAmount:', built.makingAmount)
+    console.log('takingAmount:', built.takingAmount)
+    console.log('makerTraits:', toHex32(built.makerTraits))
+    console.log('orderHash (chain 1):', order.getOrderHash(srcChainId))
+    console.log('orderHash (chain 56):', order.getOrderHash(dstChainId))
+})();
@@ -1,10 +1,61 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, contracterror,
-    Address, Bytes, BytesN, Env, symbol_short,
-    log, token
+    contract, contractclient, contractimpl, contracttype,
+    xdr::ToXdr, Address, Bytes, BytesN, Env, symbol_short,
+    token, Vec
 };
 
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+/// Maximum number of fee legs an escrow pays out (same as EscrowDst)
+pub const MAX_FEE_LEGS: u32 = 4;
+
+/// Denominator for fees expressed in basis points
+const BPS_DENOMINATOR: i128 = 10_000;
+
+/// Approximate number of ledgers closed per day
+const DAY_IN_LEDGERS: u32 = 17_280;
+
+/// Nominal ledger close time, for sequence-based timelocks checked against durations in seconds
+const LEDGER_SECONDS: u64 = 5;
+
+/// Registry entries are extended to live this many ledgers ahead...
+const REGISTRY_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+
+/// ...whenever they are touched with less than this left
+const REGISTRY_LIFETIME_THRESHOLD: u32 = REGISTRY_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+/// Maximum number of records returned by a single registry page
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// Maximum number of relayers in the attestation committee
+pub const MAX_RELAYERS: u32 = 16;
+
+/// Size of a fee leg, either fixed or in basis points of the escrowed amount (same as EscrowDst)
+#[contracttype]
+#[derive(Clone)]
+pub enum FeeAmount {
+    Fixed(i128),
+    Bps(u32),
+}
+
+/// Part of the withdrawn tokens paid to a protocol or integrator (same as EscrowDst)
+#[contracttype]
+#[derive(Clone)]
+pub struct FeeLeg {
+    pub recipient: Address,
+    pub amount: FeeAmount,
+}
+
+/// Clock the timelocks of an escrow count in (same as EscrowDst)
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeBase {
+    Timestamp,                          // Seconds of ledger close time
+    Sequence,                           // Ledger sequence numbers
+}
+
 /// Immutable parameters for the escrow (same as EscrowDst)
 #[contracttype]
 #[derive(Clone)]
@@ -12,12 +63,16 @@ pub struct Immutables {
     pub order_hash: BytesN<32>,
     pub hashlock: BytesN<32>,
     pub maker: Address,
+    pub receiver: Option<Address>,      // Paid on withdrawal instead of the maker, if set
     pub taker: Address,
     pub token: Address,
     pub amount: i128,
     pub safety_deposit: i128,
+    pub src_chain_id: u64,              // Chain the maker's tokens are locked on
+    pub dst_chain_id: u64,              // Chain the taker's tokens are paid out on
+    pub time_base: TimeBase,            // Clock the timelocks and deployed_at count in
     pub deployed_at: u64,
-    // Timelock durations in seconds from deployment
+    // Timelock durations from deployment, in seconds or ledgers per time_base
     pub src_withdrawal_start: u32,      // When taker can withdraw
     pub src_public_withdrawal_start: u32, // When anyone can withdraw for taker
     pub src_cancellation_start: u32,     // When taker can cancel
@@ -25,18 +80,100 @@ pub struct Immutables {
     pub dst_withdrawal_start: u32,      // When taker can withdraw
     pub dst_public_withdrawal_start: u32, // When anyone can withdraw for taker
     pub dst_cancellation_start: u32,     // When taker can cancel
+    pub allow_clawback: bool,           // Order accepts tokens the issuer can claw back
+    pub fees: Vec<FeeLeg>,              // Paid out of the amount on withdrawal, at most MAX_FEE_LEGS
+    // Surplus sharing, set by the factory from the order's parameters
+    pub estimated_amount: i128,         // Taking amount the order expected
+    pub surplus_percentage: u32,        // Share of the excess over the estimate paid to the protocol, 0 to 100
+    pub protocol_fee_receiver: Option<Address>, // Receives the surplus share, if any
+}
+
+/// Timelock policy for orders with one counterparty chain (same as EscrowSrcFactory)
+///
+/// Durations are in seconds; sequence-based timelocks count ledgers at the nominal close time.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TimelockPreset {
+    pub min_finality_lock: u32,         // Shortest wait before either taker can withdraw
+    pub min_private_withdrawal: u32,    // Shortest taker-only withdrawal window on either chain
+    pub min_private_cancellation: u32,  // Shortest taker-only cancellation window on the source chain
+    pub max_duration: u32,              // Latest start of the last stage on either chain
+}
+
+/// Registry record of an escrow created by this factory (same as EscrowSrcFactory)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowRecord {
+    pub escrow: Address,
+    pub order_hash: BytesN<32>,
+    pub maker: Address,
+    pub taker: Address,
+    pub created_at: u64,
+    // Positions of the escrow in the order, maker and taker indices
+    pub order_index: u32,
+    pub maker_index: u32,
+    pub taker_index: u32,
+}
+
+/// Page of registry records and the cursor to continue from, if any are left (same as EscrowSrcFactory)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowPage {
+    pub records: Vec<EscrowRecord>,
+    pub next_cursor: Option<u32>,
+}
+
+/// Registry index an escrow is listed under (same as EscrowSrcFactory)
+#[contracttype]
+#[derive(Clone)]
+pub enum RegistryIndex {
+    Order(BytesN<32>),
+    Maker(Address),
+    Taker(Address),
+}
+
+/// Relayer keys attesting source escrows, and how many of them must sign each
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Committee {
+    pub relayers: Vec<BytesN<32>>,      // ed25519 public keys
+    pub threshold: u32,
+}
+
+/// Signature of one relayer over an attestation digest
+#[contracttype]
+#[derive(Clone)]
+pub struct RelayerSignature {
+    pub public_key: BytesN<32>,
+    pub signature: BytesN<64>,
+}
+
+/// Relayers' evidence that the source escrow matching a destination escrow exists
+#[contracttype]
+#[derive(Clone)]
+pub struct Attestation {
+    pub src_chain_id: u64,
+    pub src_escrow: Bytes,              // Address of the source escrow in its chain's format
+    pub signatures: Vec<RelayerSignature>,
+}
+
+/// Interface of the EscrowDst contracts deployed by this factory
+#[contractclient(name = "EscrowDstClient")]
+pub trait EscrowDstInterface {
+    fn init(
+        env: Env,
+        deployer: Address,
+        salt: BytesN<32>,
+        immutables: Immutables,
+        native_token: Address,
+    ) -> Result<(), Error>;
+    fn finalize(env: Env) -> Result<(), Error>;
 }
 
-/// Error codes for the factory
-#[contracterror]
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
-#[repr(u32)]
-pub enum Error {
-    InsufficientEscrowBalance = 1,
-    InvalidCreationTime = 2,
-    TransferFailed = 3,
-    InvalidImmutables = 4,
-    EscrowCreationFailed = 5,
+/// Interface of the FeeBank charging resolvers for each fill
+#[contractclient(name = "FeeBankClient")]
+pub trait FeeBankInterface {
+    fn charge(env: Env, charger: Address, resolver: Address, amount: i128) -> Result<i128, Error>;
 }
 
 #[contract]
@@ -44,48 +181,305 @@ pub struct EscrowDstFactory;
 
 #[contractimpl]
 impl EscrowDstFactory {
+    /// Configure the factory with its admin, the EscrowDst wasm, the native XLM asset contract
+    /// and the chain ID orders name for this network
+    pub fn __constructor(env: Env, admin: Address, escrow_wasm_hash: BytesN<32>, native_token: Address, chain_id: u64) {
+        env.storage().instance().set(&symbol_short!("admin"), &admin);
+        env.storage().instance().set(&symbol_short!("wasm"), &escrow_wasm_hash);
+        env.storage().instance().set(&symbol_short!("native"), &native_token);
+        env.storage().instance().set(&symbol_short!("chain"), &chain_id);
+    }
+
+    /// Get the chain ID orders must name as their destination chain
+    pub fn get_chain_id(env: Env) -> u64 {
+        env.storage().instance().get(&symbol_short!("chain")).unwrap()
+    }
+
     /// Create a new destination escrow contract
     /// This function maps the createDstEscrow functionality from BaseEscrowFactory
+    ///
+    /// A non-zero `surplus_percentage` routes that share of any fill above
+    /// `estimated_taking_amount` to the protocol fee receiver on withdrawal. Escrows
+    /// naming a `receiver` have to go through `create_dst_escrow_attested`, as only the
+    /// committee can vouch that the order names that receiver.
     pub fn create_dst_escrow(
         env: Env,
         dst_immutables: Immutables,
         src_cancellation_timestamp: u64,
+        estimated_taking_amount: i128,
+        surplus_percentage: u32,
     ) -> Result<Address, Error> {
         // Validate the caller is the taker
         dst_immutables.taker.require_auth();
+        Self::require_attestation_optional(&env, &dst_immutables)?;
 
-        // Check that the escrow cancellation will start not later than the cancellation time on the source chain
-        let dst_cancellation_time = dst_immutables.deployed_at + dst_immutables.dst_cancellation_start as u64;
-        if dst_cancellation_time > src_cancellation_timestamp {
-            return Err(Error::InvalidCreationTime);
+        Self::create_escrow(&env, dst_immutables, src_cancellation_timestamp, estimated_taking_amount, surplus_percentage)
+    }
+
+    /// Create a destination escrow backed by the relayer committee's attestation of its source escrow
+    ///
+    /// A threshold of committee relayers must have signed the attestation digest for
+    /// these arguments; `create_dst_escrow` is refused while a committee is set. The
+    /// digest covers the whole immutables, so the escrow pays the receiver the relayers
+    /// found in the source order.
+    pub fn create_dst_escrow_attested(
+        env: Env,
+        dst_immutables: Immutables,
+        src_cancellation_timestamp: u64,
+        estimated_taking_amount: i128,
+        surplus_percentage: u32,
+        attestation: Attestation,
+    ) -> Result<Address, Error> {
+        // Validate the caller is the taker
+        dst_immutables.taker.require_auth();
+        Self::verify_attestation(
+            &env,
+            &dst_immutables,
+            src_cancellation_timestamp,
+            estimated_taking_amount,
+            surplus_percentage,
+            &attestation,
+        )?;
+
+        Self::create_escrow(&env, dst_immutables, src_cancellation_timestamp, estimated_taking_amount, surplus_percentage)
+    }
+
+    /// Create several destination escrows at once, atomically
+    ///
+    /// Each entry holds the arguments of `create_dst_escrow` after the immutables.
+    /// Every taker authorizes the batch once; if any escrow fails the whole batch is reverted.
+    pub fn create_dst_escrows(env: Env, escrows: Vec<(Immutables, u64, i128, u32)>) -> Result<Vec<Address>, Error> {
+        let mut addresses = Vec::new(&env);
+        let mut authorized: Vec<Address> = Vec::new(&env);
+        for (dst_immutables, src_cancellation_timestamp, estimated_taking_amount, surplus_percentage) in escrows.iter() {
+            // Validate the caller is the taker, once per taker
+            if !authorized.contains(&dst_immutables.taker) {
+                dst_immutables.taker.require_auth();
+                authorized.push_back(dst_immutables.taker.clone());
+            }
+            Self::require_attestation_optional(&env, &dst_immutables)?;
+
+            let escrow_address = Self::create_escrow(
+                &env,
+                dst_immutables,
+                src_cancellation_timestamp,
+                estimated_taking_amount,
+                surplus_percentage,
+            )?;
+            addresses.push_back(escrow_address);
         }
+        Ok(addresses)
+    }
 
-        // Create salt from immutables hash
-        let salt = Self::compute_salt(&env, &dst_immutables);
+    /// Mark a token whose issuer can claw back balances (admin only)
+    ///
+    /// The Stellar Asset Contract does not expose issuer flags, so clawback-enabled
+    /// assets have to be registered here to be refused by `create_dst_escrow`.
+    pub fn set_clawback_token(env: Env, token: Address, clawback_enabled: bool) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
 
-        // Compute the escrow address
-        let escrow_address = Self::compute_escrow_address(env.clone(), dst_immutables.clone());
+        let key = (symbol_short!("clawback"), token);
+        if clawback_enabled {
+            env.storage().persistent().set(&key, &true);
+        } else {
+            env.storage().persistent().remove(&key);
+        }
+    }
 
-        // Note: In Soroban, token transfers and native XLM transfers work differently than Ethereum
-        // The taker would need to:
-        // 1. Authorize token transfers to the escrow
-        // 2. Send native XLM to the escrow address
-        // 3. The factory then deploys and initializes the escrow
-        
-        // Log the requirements for the taker
-        log!(&env, "EscrowCreationRequirements", 
-              escrow_address, 
-              dst_immutables.safety_deposit, 
-              dst_immutables.token, 
-              dst_immutables.amount);
+    /// Check whether a token is registered as clawback-enabled
+    pub fn is_clawback_token(env: Env, token: Address) -> bool {
+        env.storage().persistent().has(&(symbol_short!("clawback"), token))
+    }
 
-        // Initialize the escrow with the immutables
-        Self::init_escrow(&env, &escrow_address, &salt, &dst_immutables)?;
+    /// Set the timelock policy for orders with a counterparty chain (admin only)
+    ///
+    /// Orders with a chain that has no preset are not checked. A preset whose minimum
+    /// windows don't fit within its maximum duration is refused with `InvalidTimelockPreset`.
+    pub fn set_timelock_preset(env: Env, chain_id: u64, preset: TimelockPreset) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        let min_duration = preset.min_finality_lock as u64
+            + preset.min_private_withdrawal as u64
+            + preset.min_private_cancellation as u64;
+        if min_duration > preset.max_duration as u64 {
+            return Err(Error::InvalidTimelockPreset);
+        }
+        env.storage().persistent().set(&(symbol_short!("preset"), chain_id), &preset);
+        Ok(())
+    }
 
-        // Log the creation event
-        log!(&env, "DstEscrowCreated", escrow_address, dst_immutables.hashlock, dst_immutables.taker);
+    /// Remove the timelock policy for orders with a counterparty chain (admin only)
+    pub fn remove_timelock_preset(env: Env, chain_id: u64) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
 
-        Ok(escrow_address)
+        env.storage().persistent().remove(&(symbol_short!("preset"), chain_id));
+    }
+
+    /// Get the timelock policy for orders with a counterparty chain, if set
+    pub fn get_timelock_preset(env: Env, chain_id: u64) -> Option<TimelockPreset> {
+        env.storage().persistent().get(&(symbol_short!("preset"), chain_id))
+    }
+
+    /// Set the maximum total fee, in basis points of the amount, an escrow may pay out (admin only)
+    pub fn set_max_fee_bps(env: Env, max_fee_bps: u32) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        env.storage().instance().set(&symbol_short!("maxfee"), &max_fee_bps);
+    }
+
+    /// Get the maximum total fee in basis points (no fees are allowed until configured)
+    pub fn get_max_fee_bps(env: Env) -> u32 {
+        env.storage().instance().get(&symbol_short!("maxfee")).unwrap_or(0)
+    }
+
+    /// Set the address receiving the protocol's share of fill surpluses (admin only)
+    pub fn set_protocol_fee_receiver(env: Env, receiver: Address) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        env.storage().instance().set(&symbol_short!("feerecv"), &receiver);
+    }
+
+    /// Get the protocol fee receiver, if configured
+    pub fn get_protocol_fee_receiver(env: Env) -> Option<Address> {
+        env.storage().instance().get(&symbol_short!("feerecv"))
+    }
+
+    /// Charge the taker (resolver) `fill_fee` from its FeeBank balance on every fill (admin only)
+    pub fn set_fee_bank(env: Env, fee_bank: Address, fill_fee: i128) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        if fill_fee < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        env.storage().instance().set(&symbol_short!("feebank"), &fee_bank);
+        env.storage().instance().set(&symbol_short!("fillfee"), &fill_fee);
+        Ok(())
+    }
+
+    /// Set the relayer committee attesting source escrows (admin only)
+    ///
+    /// A zero threshold removes the committee and makes attestations optional again. The
+    /// committee is refused with `InvalidThreshold` unless it has at most `MAX_RELAYERS`
+    /// distinct relayers and at least `threshold` of them.
+    pub fn set_attestation_committee(env: Env, relayers: Vec<BytesN<32>>, threshold: u32) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        if threshold == 0 {
+            env.storage().instance().remove(&symbol_short!("committee"));
+            return Ok(());
+        }
+        if threshold > relayers.len() || relayers.len() > MAX_RELAYERS {
+            return Err(Error::InvalidThreshold);
+        }
+        for (i, relayer) in relayers.iter().enumerate() {
+            if relayers.first_index_of(&relayer) != Some(i as u32) {
+                return Err(Error::InvalidThreshold);
+            }
+        }
+
+        env.storage().instance().set(&symbol_short!("committee"), &Committee { relayers, threshold });
+        Ok(())
+    }
+
+    /// Get the relayer committee, if configured
+    pub fn get_attestation_committee(env: Env) -> Option<Committee> {
+        env.storage().instance().get(&symbol_short!("committee"))
+    }
+
+    /// Compute the digest relayers sign to attest the source escrow of a destination escrow
+    ///
+    /// It is the SHA-256 of the XDR of `(src_chain_id, src_escrow, immutables_hash,
+    /// src_cancellation_timestamp, estimated_taking_amount, surplus_percentage)`, where
+    /// `immutables_hash` is the SHA-256 of the XDR of the destination immutables as the
+    /// escrow will store them, with the surplus share and the protocol fee receiver set
+    /// by this factory, and the other arguments are as passed to `create_dst_escrow_attested`.
+    pub fn compute_attestation_digest(
+        env: Env,
+        src_chain_id: u64,
+        src_escrow: Bytes,
+        dst_immutables: Immutables,
+        src_cancellation_timestamp: u64,
+        estimated_taking_amount: i128,
+        surplus_percentage: u32,
+    ) -> Result<BytesN<32>, Error> {
+        let mut dst_immutables = dst_immutables;
+        Self::set_surplus_share(&env, &mut dst_immutables, estimated_taking_amount, surplus_percentage)?;
+
+        let immutables_hash: BytesN<32> = env.crypto().sha256(&dst_immutables.to_xdr(&env)).into();
+        let message = (
+            src_chain_id,
+            src_escrow,
+            immutables_hash,
+            src_cancellation_timestamp,
+            estimated_taking_amount,
+            surplus_percentage,
+        )
+            .to_xdr(&env);
+        Ok(env.crypto().sha256(&message).into())
+    }
+
+    /// Get the FeeBank resolvers are charged from, if configured
+    pub fn get_fee_bank(env: Env) -> Option<Address> {
+        env.storage().instance().get(&symbol_short!("feebank"))
+    }
+
+    /// Get the fee charged per fill
+    pub fn get_fill_fee(env: Env) -> i128 {
+        env.storage().instance().get(&symbol_short!("fillfee")).unwrap_or(0)
+    }
+
+    /// Get the registry record of an escrow created by this factory
+    pub fn get_escrow(env: Env, escrow: Address) -> Option<EscrowRecord> {
+        env.storage().persistent().get(&(symbol_short!("escrow"), escrow))
+    }
+
+    /// List the escrows created for an order, `limit` at a time starting at `cursor`
+    pub fn escrows_by_order(env: Env, order_hash: BytesN<32>, cursor: u32, limit: u32) -> EscrowPage {
+        Self::page(&env, RegistryIndex::Order(order_hash), cursor, limit)
+    }
+
+    /// List the escrows created for a maker, `limit` at a time starting at `cursor`
+    pub fn escrows_by_maker(env: Env, maker: Address, cursor: u32, limit: u32) -> EscrowPage {
+        Self::page(&env, RegistryIndex::Maker(maker), cursor, limit)
+    }
+
+    /// List the escrows created for a taker, `limit` at a time starting at `cursor`
+    pub fn escrows_by_taker(env: Env, taker: Address, cursor: u32, limit: u32) -> EscrowPage {
+        Self::page(&env, RegistryIndex::Taker(taker), cursor, limit)
+    }
+
+    /// Keep an escrow's registry entries live (anyone can pay for the extension)
+    pub fn extend_escrow_ttl(env: Env, escrow: Address) -> Result<(), Error> {
+        let record = Self::get_escrow(env.clone(), escrow).ok_or(Error::InvalidAddress)?;
+        Self::extend_record_ttl(&env, &record);
+        Ok(())
+    }
+
+    /// Finalize a withdrawn or cancelled escrow and drop it from the registry
+    ///
+    /// Anyone can pay for the cleanup; the escrow checks it is settled and empty.
+    pub fn finalize_escrow(env: Env, escrow: Address) -> Result<(), Error> {
+        let record = Self::get_escrow(env.clone(), escrow.clone()).ok_or(Error::InvalidAddress)?;
+
+        // Escrow errors share the factory's codes and are passed through as-is
+        match EscrowDstClient::new(&env, &escrow).try_finalize() {
+            Ok(Ok(())) => {}
+            Err(Ok(error)) => return Err(error),
+            _ => return Err(Error::FinalizationFailed),
+        }
+
+        Self::remove_record(&env, &record);
+
+        env.events().publish((symbol_short!("finalize"),), escrow);
+
+        Ok(())
     }
 
     /// Compute the deterministic address for an escrow
@@ -99,15 +493,383 @@ impl EscrowDstFactory {
     }
 
     /// Compute salt from immutables (similar to hashMem in Ethereum)
+    ///
+    /// The salt is the SHA-256 of the network ID and the XDR of the immutables, so an
+    /// escrow paying out differently than the order says (receiver, fee legs, surplus
+    /// share, chain IDs...) ends up at a different address, and testnet and mainnet
+    /// escrows stay apart. `deployed_at` is zeroed as the escrow sets it on initialization.
     pub(crate) fn compute_salt(env: &Env, immutables: &Immutables) -> BytesN<32> {
-        // Create a deterministic salt from key immutables
-        let mut salt_array = [0u8; 32];
-        
-        // Use order_hash and hashlock for deterministic salt
-        salt_array[..16].copy_from_slice(&immutables.order_hash.to_array()[..16]);
-        salt_array[16..].copy_from_slice(&immutables.hashlock.to_array()[..16]);
-        
-        BytesN::from_array(env, &salt_array)
+        let mut immutables = immutables.clone();
+        immutables.deployed_at = 0;
+
+        let mut data = Bytes::from_array(env, &env.ledger().network_id().to_array());
+        data.append(&immutables.to_xdr(env));
+        env.crypto().sha256(&data).into()
+    }
+
+    /// Size of a single fee leg, or `None` if it is malformed
+    fn fee_amount(immutables: &Immutables, leg: &FeeLeg) -> Option<i128> {
+        match leg.amount {
+            FeeAmount::Fixed(amount) if amount >= 0 => Some(amount),
+            FeeAmount::Bps(bps) if (bps as i128) <= BPS_DENOMINATOR => {
+                Some(immutables.amount.checked_mul(bps as i128)? / BPS_DENOMINATOR)
+            }
+            _ => None,
+        }
+    }
+
+    /// Protocol's share of the amount filled above the order's estimate, or `None` if malformed
+    fn surplus_fee(immutables: &Immutables) -> Option<i128> {
+        if immutables.estimated_amount < 0 || immutables.surplus_percentage > 100 {
+            return None;
+        }
+        if immutables.protocol_fee_receiver.is_none() || immutables.amount <= immutables.estimated_amount {
+            return Some(0);
+        }
+        let excess = immutables.amount - immutables.estimated_amount;
+        Some(excess.checked_mul(immutables.surplus_percentage as i128)? / 100)
+    }
+
+    /// Sum of all fee legs, or `None` if there are too many or one is malformed
+    fn total_fees(immutables: &Immutables) -> Option<i128> {
+        if immutables.fees.len() > MAX_FEE_LEGS {
+            return None;
+        }
+        let mut total: i128 = 0;
+        for leg in immutables.fees.iter() {
+            total = total.checked_add(Self::fee_amount(immutables, &leg)?)?;
+        }
+        Some(total)
+    }
+
+    /// Reject immutables the escrow would refuse to initialize with
+    fn validate_immutables(immutables: &Immutables) -> Result<(), Error> {
+        if immutables.amount <= 0 || immutables.safety_deposit < 0 {
+            return Err(Error::InvalidImmutables);
+        }
+        match (Self::total_fees(immutables), Self::surplus_fee(immutables)) {
+            (Some(total), Some(surplus_fee))
+                if total.checked_add(surplus_fee).is_some_and(|fees| fees <= immutables.amount) => {}
+            _ => return Err(Error::InvalidImmutables),
+        }
+        if immutables.dst_withdrawal_start > immutables.dst_public_withdrawal_start
+            || immutables.dst_public_withdrawal_start >= immutables.dst_cancellation_start
+        {
+            return Err(Error::InvalidImmutables);
+        }
+        Ok(())
+    }
+
+    /// Deploy, fund and initialize a destination escrow once the taker has authorized it
+    fn create_escrow(
+        env: &Env,
+        mut dst_immutables: Immutables,
+        src_cancellation_timestamp: u64,
+        estimated_taking_amount: i128,
+        surplus_percentage: u32,
+    ) -> Result<Address, Error> {
+        Self::set_surplus_share(env, &mut dst_immutables, estimated_taking_amount, surplus_percentage)?;
+
+        Self::validate_immutables(&dst_immutables)?;
+        Self::require_chain_id(env, &dst_immutables)?;
+        Self::require_timelock_preset(env, &dst_immutables)?;
+        Self::require_clawback_allowed(env, &dst_immutables)?;
+        Self::require_fees_within_cap(env, &dst_immutables)?;
+        Self::charge_fill_fee(env, &dst_immutables.taker)?;
+        Self::validate_receiver(env, &dst_immutables)?;
+
+        // Check that the escrow cancellation will start not later than the cancellation time on the source chain
+        // (the escrow records the current ledger time as its deployment time; sequence-based
+        // timelocks are estimated at the nominal ledger close time)
+        let dst_cancellation_time =
+            env.ledger().timestamp() + Self::offset_seconds(&dst_immutables, dst_immutables.dst_cancellation_start);
+        if dst_cancellation_time > src_cancellation_timestamp {
+            return Err(Error::InvalidCreationTime);
+        }
+
+        // Create salt from immutables hash
+        let salt = Self::compute_salt(env, &dst_immutables);
+
+        // Note: In Soroban, token transfers and native XLM transfers work differently than Ethereum
+        // The safety deposit (native XLM) must already have been sent to the escrow address,
+        // while the tokens are pulled from the taker here under the taker's authorization
+
+        // Deploy the escrow at the address derived from the salt
+        let escrow_address = Self::deploy_escrow(env, &salt);
+
+        // Pull the taker's tokens into the escrow
+        Self::pull_funds(env, &dst_immutables.token, &dst_immutables.taker, &escrow_address, dst_immutables.amount)?;
+
+        // Initialize the escrow with the immutables
+        Self::init_escrow(env, &escrow_address, &salt, &dst_immutables)?;
+
+        Self::record_escrow(env, &escrow_address, &dst_immutables);
+
+        env.events().publish(
+            (symbol_short!("created"), dst_immutables.hashlock),
+            (escrow_address.clone(), dst_immutables.taker),
+        );
+
+        Ok(escrow_address)
+    }
+
+    /// Record the order's surplus parameters and the protocol fee receiver in the immutables
+    fn set_surplus_share(
+        env: &Env,
+        immutables: &mut Immutables,
+        estimated_taking_amount: i128,
+        surplus_percentage: u32,
+    ) -> Result<(), Error> {
+        immutables.estimated_amount = estimated_taking_amount;
+        immutables.surplus_percentage = surplus_percentage;
+        immutables.protocol_fee_receiver = None;
+        if surplus_percentage > 0 {
+            let receiver = Self::get_protocol_fee_receiver(env.clone()).ok_or(Error::FeeReceiverNotSet)?;
+            immutables.protocol_fee_receiver = Some(receiver);
+        }
+        Ok(())
+    }
+
+    /// Refuse unattested escrows while a relayer committee is set, or when they name a receiver
+    fn require_attestation_optional(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        if Self::get_attestation_committee(env.clone()).is_some() || immutables.receiver.is_some() {
+            return Err(Error::AttestationRequired);
+        }
+        Ok(())
+    }
+
+    /// Check that a threshold of distinct committee relayers signed the attestation
+    ///
+    /// Signers from outside the committee, repeated signers and too few signers are
+    /// rejected with an error before any signature is checked. A signature that does
+    /// not verify then traps in the host, which has no fallible ed25519 check, so the
+    /// call aborts without an error code.
+    fn verify_attestation(
+        env: &Env,
+        immutables: &Immutables,
+        src_cancellation_timestamp: u64,
+        estimated_taking_amount: i128,
+        surplus_percentage: u32,
+        attestation: &Attestation,
+    ) -> Result<(), Error> {
+        let committee = Self::get_attestation_committee(env.clone()).ok_or(Error::NotInitialized)?;
+        if attestation.src_chain_id != immutables.src_chain_id {
+            return Err(Error::ChainIdMismatch);
+        }
+
+        let mut signers: Vec<BytesN<32>> = Vec::new(env);
+        for signature in attestation.signatures.iter() {
+            if !committee.relayers.contains(&signature.public_key) || signers.contains(&signature.public_key) {
+                return Err(Error::InvalidSignature);
+            }
+            signers.push_back(signature.public_key);
+        }
+        if signers.len() < committee.threshold {
+            return Err(Error::InsufficientAttestations);
+        }
+
+        let digest = Self::compute_attestation_digest(
+            env.clone(),
+            attestation.src_chain_id,
+            attestation.src_escrow.clone(),
+            immutables.clone(),
+            src_cancellation_timestamp,
+            estimated_taking_amount,
+            surplus_percentage,
+        )?;
+        for signature in attestation.signatures.iter() {
+            env.crypto().ed25519_verify(&signature.public_key, &digest.clone().into(), &signature.signature);
+        }
+        env.events().publish(
+            (symbol_short!("attested"), attestation.src_escrow.clone()),
+            (attestation.src_chain_id, signers.len()),
+        );
+        Ok(())
+    }
+
+    /// Refuse paying the withdrawn tokens to the factory, which could never move them on
+    ///
+    /// The escrow's own address depends on the receiver through the salt, so it
+    /// cannot be named as receiver. That the order names the receiver is attested by
+    /// the committee, unattested escrows cannot name one.
+    fn validate_receiver(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        if immutables.receiver == Some(env.current_contract_address()) {
+            return Err(Error::InvalidAddress);
+        }
+        Ok(())
+    }
+
+    /// Refuse orders naming another chain than this one as their destination
+    fn require_chain_id(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        if immutables.dst_chain_id != Self::get_chain_id(env.clone()) {
+            return Err(Error::ChainIdMismatch);
+        }
+        Ok(())
+    }
+
+    /// Refuse timelocks outside the preset for the order's source chain, if there is one
+    fn require_timelock_preset(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        let Some(preset) = Self::get_timelock_preset(env.clone(), immutables.src_chain_id) else {
+            return Ok(());
+        };
+        let seconds = |offset: u32| Self::offset_seconds(immutables, offset);
+        let src_private_withdrawal = immutables.src_public_withdrawal_start.saturating_sub(immutables.src_withdrawal_start);
+        let dst_private_withdrawal = immutables.dst_public_withdrawal_start.saturating_sub(immutables.dst_withdrawal_start);
+        let src_private_cancellation =
+            immutables.src_public_cancellation_start.saturating_sub(immutables.src_cancellation_start);
+        if seconds(immutables.src_withdrawal_start) < preset.min_finality_lock as u64
+            || seconds(immutables.dst_withdrawal_start) < preset.min_finality_lock as u64
+            || seconds(src_private_withdrawal) < preset.min_private_withdrawal as u64
+            || seconds(dst_private_withdrawal) < preset.min_private_withdrawal as u64
+            || seconds(src_private_cancellation) < preset.min_private_cancellation as u64
+            || seconds(immutables.src_public_cancellation_start) > preset.max_duration as u64
+            || seconds(immutables.dst_cancellation_start) > preset.max_duration as u64
+        {
+            return Err(Error::TimelockOutOfPolicy);
+        }
+        Ok(())
+    }
+
+    /// Timelock offset in seconds, counting ledgers at the nominal close time
+    fn offset_seconds(immutables: &Immutables, offset: u32) -> u64 {
+        match immutables.time_base {
+            TimeBase::Timestamp => offset as u64,
+            TimeBase::Sequence => offset as u64 * LEDGER_SECONDS,
+        }
+    }
+
+    /// Refuse clawback-enabled tokens unless the order explicitly accepts them
+    fn require_clawback_allowed(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        if !immutables.allow_clawback && Self::is_clawback_token(env.clone(), immutables.token.clone()) {
+            return Err(Error::ClawbackNotAllowed);
+        }
+        Ok(())
+    }
+
+    /// Refuse fee legs adding up to more than the configured maximum
+    fn require_fees_within_cap(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        let total = Self::total_fees(immutables).ok_or(Error::InvalidImmutables)?;
+        let max_fee_bps = Self::get_max_fee_bps(env.clone()) as i128;
+
+        // Fees too large to weigh exceed any cap, a cap too large to weigh is no limit
+        let fees = total.checked_mul(BPS_DENOMINATOR).ok_or(Error::FeeTooHigh)?;
+        match immutables.amount.checked_mul(max_fee_bps) {
+            Some(cap) if fees > cap => Err(Error::FeeTooHigh),
+            _ => Ok(()),
+        }
+    }
+
+    /// Debit the per-fill fee from the resolver's FeeBank balance, if a fee bank is configured
+    fn charge_fill_fee(env: &Env, resolver: &Address) -> Result<(), Error> {
+        let fee_bank = match Self::get_fee_bank(env.clone()) {
+            Some(fee_bank) => fee_bank,
+            None => return Ok(()),
+        };
+        let fill_fee = Self::get_fill_fee(env.clone());
+        if fill_fee == 0 {
+            return Ok(());
+        }
+
+        // FeeBank errors share the factory's codes and are passed through as-is
+        match FeeBankClient::new(env, &fee_bank).try_charge(&env.current_contract_address(), resolver, &fill_fee) {
+            Ok(Ok(_)) => Ok(()),
+            Err(Ok(error)) => Err(error),
+            _ => Err(Error::EscrowCreationFailed),
+        }
+    }
+
+    /// Transfer tokens into the escrow, checking that exactly `amount` arrived
+    ///
+    /// Fee-on-transfer and rebasing tokens deliver a different amount and are rejected.
+    fn pull_funds(env: &Env, token: &Address, from: &Address, escrow: &Address, amount: i128) -> Result<(), Error> {
+        let token_client = token::Client::new(env, token);
+        let balance_before = token_client.balance(escrow);
+
+        match token_client.try_transfer(from, escrow, &amount) {
+            Ok(Ok(())) => {}
+            _ => return Err(Error::TransferFailed),
+        }
+
+        if token_client.balance(escrow) - balance_before != amount {
+            return Err(Error::BalanceMismatch);
+        }
+        Ok(())
+    }
+
+    /// Deploy the EscrowDst contract at the address derived from the salt
+    fn deploy_escrow(env: &Env, salt: &BytesN<32>) -> Address {
+        let wasm_hash: BytesN<32> = env.storage().instance().get(&symbol_short!("wasm")).unwrap();
+        env.deployer().with_current_contract(salt.clone()).deploy_v2(wasm_hash, ())
+    }
+
+    /// Add a newly created escrow to the order, maker and taker indices
+    fn record_escrow(env: &Env, escrow: &Address, immutables: &Immutables) {
+        let record = EscrowRecord {
+            escrow: escrow.clone(),
+            order_hash: immutables.order_hash.clone(),
+            maker: immutables.maker.clone(),
+            taker: immutables.taker.clone(),
+            created_at: env.ledger().timestamp(),
+            order_index: Self::append(env, RegistryIndex::Order(immutables.order_hash.clone()), escrow),
+            maker_index: Self::append(env, RegistryIndex::Maker(immutables.maker.clone()), escrow),
+            taker_index: Self::append(env, RegistryIndex::Taker(immutables.taker.clone()), escrow),
+        };
+        env.storage().persistent().set(&(symbol_short!("escrow"), escrow.clone()), &record);
+        Self::extend_record_ttl(env, &record);
+    }
+
+    /// Append an escrow to an index, returning its position
+    fn append(env: &Env, index: RegistryIndex, escrow: &Address) -> u32 {
+        let count_key = (symbol_short!("count"), index.clone());
+        let position: u32 = env.storage().persistent().get(&count_key).unwrap_or(0);
+        env.storage().persistent().set(&(symbol_short!("slot"), index, position), escrow);
+        env.storage().persistent().set(&count_key, &(position + 1));
+        position
+    }
+
+    /// Remove a record and its index slots; the pages skip the emptied slots
+    fn remove_record(env: &Env, record: &EscrowRecord) {
+        let storage = env.storage().persistent();
+        storage.remove(&(symbol_short!("escrow"), record.escrow.clone()));
+        storage.remove(&(symbol_short!("slot"), RegistryIndex::Order(record.order_hash.clone()), record.order_index));
+        storage.remove(&(symbol_short!("slot"), RegistryIndex::Maker(record.maker.clone()), record.maker_index));
+        storage.remove(&(symbol_short!("slot"), RegistryIndex::Taker(record.taker.clone()), record.taker_index));
+    }
+
+    /// Read the records of an index at positions `cursor` to `cursor + limit`
+    ///
+    /// Slots emptied by finalization are skipped without reading further, so a page can
+    /// hold fewer than `limit` records while a cursor is still handed back.
+    fn page(env: &Env, index: RegistryIndex, cursor: u32, limit: u32) -> EscrowPage {
+        let count: u32 = env.storage().persistent().get(&(symbol_short!("count"), index.clone())).unwrap_or(0);
+        let end = cursor.saturating_add(limit.min(MAX_PAGE_SIZE)).min(count);
+
+        let mut records = Vec::new(env);
+        for position in cursor..end {
+            let slot: Option<Address> = env.storage().persistent().get(&(symbol_short!("slot"), index.clone(), position));
+            if let Some(record) = slot.and_then(|escrow| Self::get_escrow(env.clone(), escrow)) {
+                records.push_back(record);
+            }
+        }
+
+        let next_cursor = if end < count { Some(end) } else { None };
+        EscrowPage { records, next_cursor }
+    }
+
+    /// Extend the TTL of a record, its index slots and the index counters
+    fn extend_record_ttl(env: &Env, record: &EscrowRecord) {
+        let storage = env.storage().persistent();
+        storage.extend_ttl(&(symbol_short!("escrow"), record.escrow.clone()), REGISTRY_LIFETIME_THRESHOLD, REGISTRY_BUMP_AMOUNT);
+
+        let indices = [
+            (RegistryIndex::Order(record.order_hash.clone()), record.order_index),
+            (RegistryIndex::Maker(record.maker.clone()), record.maker_index),
+            (RegistryIndex::Taker(record.taker.clone()), record.taker_index),
+        ];
+        for (index, position) in indices {
+            storage.extend_ttl(&(symbol_short!("count"), index.clone()), REGISTRY_LIFETIME_THRESHOLD, REGISTRY_BUMP_AMOUNT);
+            storage.extend_ttl(&(symbol_short!("slot"), index, position), REGISTRY_LIFETIME_THRESHOLD, REGISTRY_BUMP_AMOUNT);
+        }
     }
 
     /// Initialize the escrow contract
@@ -115,17 +877,18 @@ impl EscrowDstFactory {
         env: &Env,
         escrow_address: &Address,
         salt: &BytesN<32>,
-        _immutables: &Immutables,
+        immutables: &Immutables,
     ) -> Result<(), Error> {
-        // In a real implementation, you would:
-        // 1. Deploy the EscrowDst contract to the computed address
-        // 2. Call the init function on the deployed escrow contract
-        // 3. Pass the immutables and other parameters
-        // 4. Handle any errors from the initialization
-        
-        // For now, we'll simulate the initialization
-        log!(&env, "EscrowInitialized", escrow_address, salt);
-        
+        let native_token: Address = env.storage().instance().get(&symbol_short!("native")).unwrap();
+        let escrow = EscrowDstClient::new(env, escrow_address);
+
+        // Escrow errors share the factory's codes and are passed through as-is
+        match escrow.try_init(&env.current_contract_address(), salt, immutables, &native_token) {
+            Ok(Ok(())) => {}
+            Err(Ok(error)) => return Err(error),
+            _ => return Err(Error::EscrowCreationFailed),
+        }
+
         Ok(())
     }
 }
@@ -133,28 +896,94 @@ impl EscrowDstFactory {
 #[cfg(test)]
 mod test {
     use super::*;
+    use escrow_dst::State;
     use soroban_sdk::{
-        Address, BytesN, Env, 
-        testutils::{Address as _, Ledger as _}
+        Address, Bytes, BytesN, Env, vec, IntoVal, InvokeError, Val,
+        testutils::{Address as _, Events as _, Ledger as _, storage::Persistent},
+        token::{StellarAssetClient, TokenClient},
     };
 
-    #[test]
-    fn test_create_dst_escrow() {
+    /// EscrowDst wasm the factory deploys (build it with `make build` first)
+    mod escrow_wasm {
+        soroban_sdk::contractimport!(file = "../../target/wasm32v1-none/release/escrow_dst.wasm");
+    }
+
+    /// EscrowSrc wasm, whose `init` the factory cannot call
+    mod wrong_escrow_wasm {
+        soroban_sdk::contractimport!(file = "../../target/wasm32v1-none/release/escrow_src.wasm");
+    }
+
+    /// Token whose transfers deliver one unit less than requested
+    #[contract]
+    struct LossyToken;
+
+    #[contractimpl]
+    impl LossyToken {
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let balance = Self::balance(env.clone(), to.clone());
+            env.storage().persistent().set(&to, &(balance + amount));
+        }
+
+        pub fn balance(env: Env, id: Address) -> i128 {
+            env.storage().persistent().get(&id).unwrap_or(0)
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            let from_balance = Self::balance(env.clone(), from.clone());
+            env.storage().persistent().set(&from, &(from_balance - amount));
+            Self::mint(env, to, amount - 1);
+        }
+    }
+
+    struct Setup<'a> {
+        env: Env,
+        client: EscrowDstFactoryClient<'a>,
+        native_token: Address,
+    }
+
+    fn setup<'a>() -> Setup<'a> {
+        setup_with_wasm(escrow_wasm::WASM)
+    }
+
+    /// Topics and data of the events the factory published in the last invocation
+    fn factory_events(s: &Setup) -> Vec<(Vec<Val>, Val)> {
+        let mut events = Vec::new(&s.env);
+        for (contract, topics, data) in s.env.events().all().iter() {
+            if contract == s.client.address {
+                events.push_back((topics, data));
+            }
+        }
+        events
+    }
+
+    fn setup_with_wasm<'a>(escrow_wasm: &[u8]) -> Setup<'a> {
         let env = Env::default();
-        
+        env.mock_all_auths();
+
+        let admin = Address::generate(&env);
+        let wasm_hash = env.deployer().upload_contract_wasm(escrow_wasm);
+        let native_token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+
         // Register the factory contract
-        let contract_id = env.register(EscrowDstFactory, ());
+        let contract_id = env.register(EscrowDstFactory, (admin, wasm_hash, native_token.clone(), 1000u64));
         let client = EscrowDstFactoryClient::new(&env, &contract_id);
-        
-        // Create test immutables
-        let immutables = Immutables {
-            order_hash: BytesN::from_array(&env, &[1u8; 32]),
-            hashlock: BytesN::from_array(&env, &[2u8; 32]),
-            maker: Address::generate(&env),
-            taker: Address::generate(&env),
-            token: Address::generate(&env),
+
+        Setup { env, client, native_token }
+    }
+
+    fn create_immutables(env: &Env, token: &Address) -> Immutables {
+        Immutables {
+            order_hash: BytesN::from_array(env, &[1u8; 32]),
+            hashlock: BytesN::from_array(env, &[2u8; 32]),
+            maker: Address::generate(env),
+            receiver: None,
+            taker: Address::generate(env),
+            token: token.clone(),
             amount: 1000,
             safety_deposit: 100,
+            src_chain_id: 1,
+            dst_chain_id: 1000,
+            time_base: TimeBase::Timestamp,
             deployed_at: env.ledger().timestamp(),
             src_withdrawal_start: 3600,      // 1 hour
             src_public_withdrawal_start: 7200, // 2 hours
@@ -163,35 +992,53 @@ mod test {
             dst_withdrawal_start: 3600,      // 1 hour
             dst_public_withdrawal_start: 7200, // 2 hours
             dst_cancellation_start: 10800,     // 3 hours
-        };
+            allow_clawback: false,
+            fees: Vec::new(env),
+            estimated_amount: 0,
+            surplus_percentage: 0,
+            protocol_fee_receiver: None,
+        }
+    }
 
-        // Test that we can compute the escrow address
-        let escrow_address = client.compute_escrow_address(&immutables);
-        // Just verify the function doesn't panic and returns an address
-        assert!(escrow_address.to_string().len() > 0);
+    /// Send the safety deposit to where the factory will deploy the escrow
+    fn prepare_escrow(s: &Setup, immutables: &Immutables) -> Address {
+        let escrow_address = s.client.compute_escrow_address(immutables);
+        StellarAssetClient::new(&s.env, &s.native_token).mint(&escrow_address, &immutables.safety_deposit);
+        escrow_address
+    }
+
+    /// Immutables for a Stellar asset the taker holds exactly `amount` of
+    fn funded_immutables(s: &Setup) -> Immutables {
+        let token = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+        let immutables = create_immutables(&s.env, &token);
+        StellarAssetClient::new(&s.env, &token).mint(&immutables.taker, &immutables.amount);
+        immutables
+    }
+
+    #[test]
+    fn test_create_dst_escrow() {
+        let s = setup();
+        
+        let immutables = funded_immutables(&s);
+        let escrow_address = prepare_escrow(&s, &immutables);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        assert_eq!(s.client.create_dst_escrow(&immutables, &src_cancellation_time, &0, &0), escrow_address);
+
+        // The taker's tokens moved into an initialized escrow
+        let token = TokenClient::new(&s.env, &immutables.token);
+        assert_eq!(token.balance(&escrow_address), 1000);
+        assert_eq!(token.balance(&immutables.taker), 0);
+
+        let escrow = escrow_dst::EscrowDstClient::new(&s.env, &escrow_address);
+        assert_eq!(escrow.get_state(), State::Active);
     }
 
     #[test]
     fn test_compute_salt() {
         let env = Env::default();
         
-        let immutables = Immutables {
-            order_hash: BytesN::from_array(&env, &[1u8; 32]),
-            hashlock: BytesN::from_array(&env, &[2u8; 32]),
-            maker: Address::generate(&env),
-            taker: Address::generate(&env),
-            token: Address::generate(&env),
-            amount: 1000,
-            safety_deposit: 100,
-            deployed_at: env.ledger().timestamp(),
-            src_withdrawal_start: 3600,
-            src_public_withdrawal_start: 7200,
-            src_cancellation_start: 10800,
-            src_public_cancellation_start: 14400,
-            dst_withdrawal_start: 3600,
-            dst_public_withdrawal_start: 7200,
-            dst_cancellation_start: 10800,
-        };
+        let immutables = create_immutables(&env, &Address::generate(&env));
 
         let salt = EscrowDstFactory::compute_salt(&env, &immutables);
         assert!(salt != BytesN::from_array(&env, &[0u8; 32]));
@@ -199,39 +1046,641 @@ mod test {
         // Test that same immutables produce same salt
         let salt2 = EscrowDstFactory::compute_salt(&env, &immutables);
         assert_eq!(salt, salt2);
+
+        // A different receiver gives a different escrow
+        let mut with_receiver = immutables.clone();
+        with_receiver.receiver = Some(Address::generate(&env));
+        assert!(EscrowDstFactory::compute_salt(&env, &with_receiver) != salt);
+
+        // ...but not another deployment time, which the escrow sets itself
+        let mut deployed_later = immutables.clone();
+        deployed_later.deployed_at += 60;
+        assert_eq!(EscrowDstFactory::compute_salt(&env, &deployed_later), salt);
+
+        // Another network does
+        env.ledger().set_network_id([7u8; 32]);
+        assert!(EscrowDstFactory::compute_salt(&env, &immutables) != salt);
+    }
+
+    #[test]
+    fn test_chain_ids_change_address() {
+        let s = setup();
+        let immutables = create_immutables(&s.env, &Address::generate(&s.env));
+        let address = s.client.compute_escrow_address(&immutables);
+
+        let mut other_src = immutables.clone();
+        other_src.src_chain_id = 56;
+        assert!(s.client.compute_escrow_address(&other_src) != address);
+
+        let mut other_dst = immutables.clone();
+        other_dst.dst_chain_id = 1001;
+        assert!(s.client.compute_escrow_address(&other_dst) != address);
+    }
+
+    #[test]
+    fn test_chain_id_mismatch() {
+        let s = setup();
+        assert_eq!(s.client.get_chain_id(), 1000);
+
+        // Orders must name this chain as their destination
+        let mut immutables = funded_immutables(&s);
+        immutables.dst_chain_id = 1;
+        prepare_escrow(&s, &immutables);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::ChainIdMismatch)));
+
+        // The chain ID is part of the salt, so the escrow lands at another address
+        immutables.dst_chain_id = 1000;
+        prepare_escrow(&s, &immutables);
+        s.client.create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+    }
+
+    #[test]
+    fn test_timelock_preset() {
+        let s = setup();
+        let preset = TimelockPreset {
+            min_finality_lock: 3600,
+            min_private_withdrawal: 3600,
+            min_private_cancellation: 3600,
+            max_duration: 14400,
+        };
+
+        // The minimum windows have to fit within the maximum duration
+        let too_short = TimelockPreset { max_duration: 10799, ..preset.clone() };
+        assert_eq!(s.client.try_set_timelock_preset(&1, &too_short), Err(Ok(Error::InvalidTimelockPreset)));
+
+        s.client.set_timelock_preset(&1, &preset);
+        assert_eq!(s.client.get_timelock_preset(&1), Some(preset.clone()));
+        assert_eq!(s.client.get_timelock_preset(&2), None);
+
+        let immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        // Each bound of the preset is enforced against the source chain's entry
+        let violations = [
+            TimelockPreset { min_finality_lock: 3601, ..preset.clone() },
+            TimelockPreset { min_private_withdrawal: 3601, ..preset.clone() },
+            TimelockPreset { min_private_cancellation: 3601, ..preset.clone() },
+            TimelockPreset { max_duration: 14399, ..preset.clone() },
+        ];
+        for violation in violations {
+            s.client.set_timelock_preset(&1, &violation);
+            let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+            assert_eq!(result, Err(Ok(Error::TimelockOutOfPolicy)));
+        }
+
+        // Orders to chains without a preset are not checked
+        s.client.remove_timelock_preset(&1);
+        assert_eq!(s.client.get_timelock_preset(&1), None);
+        s.client.set_timelock_preset(&2, &TimelockPreset { min_finality_lock: 7200, ..preset.clone() });
+        s.client.set_timelock_preset(&1000, &TimelockPreset { min_finality_lock: 7200, ..preset.clone() });
+        s.client.create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
     }
 
     #[test]
     fn test_invalid_creation_time() {
-        let env = Env::default();
+        let s = setup();
         
-        // Register the factory contract
-        let contract_id = env.register(EscrowDstFactory, ());
-        let client = EscrowDstFactoryClient::new(&env, &contract_id);
-        
-        let immutables = Immutables {
-            order_hash: BytesN::from_array(&env, &[1u8; 32]),
-            hashlock: BytesN::from_array(&env, &[2u8; 32]),
-            maker: Address::generate(&env),
-            taker: Address::generate(&env),
-            token: Address::generate(&env),
-            amount: 1000,
-            safety_deposit: 100,
-            deployed_at: env.ledger().timestamp(),
-            src_withdrawal_start: 3600,
-            src_public_withdrawal_start: 7200,
-            src_cancellation_start: 10800,
-            src_public_cancellation_start: 14400,
-            dst_withdrawal_start: 3600,
-            dst_public_withdrawal_start: 7200,
-            dst_cancellation_start: 10800,
-        };
+        let immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
 
         // Test with invalid creation time (dst cancellation after src cancellation)
         let src_cancellation_time = immutables.deployed_at + 5000; // 5000 seconds from deployment
         
         // Use the try_ prefixed method to get the Result
-        let result = client.try_create_dst_escrow(&immutables, &src_cancellation_time);
-        assert!(result.is_err());
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::InvalidCreationTime)));
+
+        // The deployment time is the current ledger time, not the one in the immutables
+        s.env.ledger().with_mut(|li| {
+            li.timestamp = 1000;
+        });
+        let src_cancellation_time = immutables.deployed_at + 10800;
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::InvalidCreationTime)));
+
+        // Cancellation on the destination chain starting in time is accepted
+        let src_cancellation_time = 1000 + 10800;
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_invalid_immutables() {
+        let s = setup();
+
+        let immutables = create_immutables(&s.env, &Address::generate(&s.env));
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        let mut zero_amount = immutables.clone();
+        zero_amount.amount = 0;
+        let result = s.client.try_create_dst_escrow(&zero_amount, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::InvalidImmutables)));
+
+        let mut negative_deposit = immutables.clone();
+        negative_deposit.safety_deposit = -1;
+        let result = s.client.try_create_dst_escrow(&negative_deposit, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::InvalidImmutables)));
+
+        let mut unordered = immutables.clone();
+        unordered.dst_public_withdrawal_start = unordered.dst_cancellation_start;
+        let result = s.client.try_create_dst_escrow(&unordered, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::InvalidImmutables)));
+    }
+
+    #[test]
+    fn test_fee_on_transfer_token_rejected() {
+        let s = setup();
+
+        let token = s.env.register(LossyToken, ());
+        let immutables = create_immutables(&s.env, &token);
+        LossyTokenClient::new(&s.env, &token).mint(&immutables.taker, &immutables.amount);
+        let escrow_address = prepare_escrow(&s, &immutables);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::BalanceMismatch)));
+        assert_eq!(LossyTokenClient::new(&s.env, &token).balance(&escrow_address), 0);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_transfer_failed() {
+        let s = setup();
+
+        // The taker holds none of the tokens
+        let token = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+        let immutables = create_immutables(&s.env, &token);
+        prepare_escrow(&s, &immutables);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::TransferFailed)));
+    }
+
+    #[test]
+    fn test_missing_safety_deposit() {
+        let s = setup();
+
+        let immutables = funded_immutables(&s);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+    }
+
+    #[test]
+    fn test_escrow_creation_failed() {
+        let s = setup_with_wasm(wrong_escrow_wasm::WASM);
+
+        // The deployed contract rejects the initialization call
+        let immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::EscrowCreationFailed)));
+    }
+
+    #[test]
+    fn test_clawback_token() {
+        let s = setup();
+
+        let mut immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        s.client.set_clawback_token(&immutables.token, &true);
+        assert!(s.client.is_clawback_token(&immutables.token));
+
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::ClawbackNotAllowed)));
+
+        // The order can explicitly accept the clawback risk
+        immutables.allow_clawback = true;
+        let escrow_address = prepare_escrow(&s, &immutables);
+        assert_eq!(s.client.create_dst_escrow(&immutables, &src_cancellation_time, &0, &0), escrow_address);
+    }
+
+    #[test]
+    fn test_create_dst_escrow_with_receiver() {
+        let s = setup();
+
+        let mut immutables = funded_immutables(&s);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+        let receiver = Address::generate(&s.env);
+        immutables.receiver = Some(receiver.clone());
+
+        // Only the committee can vouch for the order's receiver
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::AttestationRequired)));
+        let result = s.client.try_create_dst_escrows(&vec![&s.env, (immutables.clone(), src_cancellation_time, 0, 0)]);
+        assert_eq!(result, Err(Ok(Error::AttestationRequired)));
+
+        let [first, second, _] = set_committee(&s);
+
+        // A receiver other than the attested one is rejected
+        let attestation = attest(&s, &immutables, src_cancellation_time, &[&first, &second]);
+        let mut redirected = immutables.clone();
+        redirected.receiver = Some(Address::generate(&s.env));
+        let result = s.client.try_create_dst_escrow_attested(&redirected, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Err(InvokeError::Abort)));
+
+        // The receiver cannot be the factory, even attested
+        let mut to_factory = immutables.clone();
+        to_factory.receiver = Some(s.client.address.clone());
+        let factory_attestation = attest(&s, &to_factory, src_cancellation_time, &[&first, &second]);
+        let result =
+            s.client.try_create_dst_escrow_attested(&to_factory, &src_cancellation_time, &0, &0, &factory_attestation);
+        assert_eq!(result, Err(Ok(Error::InvalidAddress)));
+
+        let escrow_address = prepare_escrow(&s, &immutables);
+        let result = s.client.create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, escrow_address);
+
+        let escrow = escrow_dst::EscrowDstClient::new(&s.env, &escrow_address);
+        assert_eq!(escrow.get_immutables().receiver, Some(receiver));
+    }
+
+    #[test]
+    fn test_fee_cap() {
+        let s = setup();
+
+        let mut immutables = funded_immutables(&s);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+        let integrator = Address::generate(&s.env);
+        immutables.fees = vec![&s.env, FeeLeg { recipient: integrator, amount: FeeAmount::Bps(100) }];
+
+        // No fees are allowed until a maximum is configured
+        assert_eq!(s.client.get_max_fee_bps(), 0);
+        assert_eq!(s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0), Err(Ok(Error::FeeTooHigh)));
+
+        s.client.set_max_fee_bps(&100);
+        assert_eq!(s.client.get_max_fee_bps(), 100);
+
+        let mut over_cap = immutables.clone();
+        over_cap.fees.push_back(FeeLeg { recipient: Address::generate(&s.env), amount: FeeAmount::Fixed(1) });
+        let result = s.client.try_create_dst_escrow(&over_cap, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::FeeTooHigh)));
+
+        // Fees too large to weigh against the cap are rejected rather than trapping
+        let mut oversized = immutables.clone();
+        oversized.amount = i128::MAX / 2;
+        oversized.fees = vec![&s.env, FeeLeg { recipient: Address::generate(&s.env), amount: FeeAmount::Fixed(i128::MAX / 4) }];
+        assert_eq!(s.client.try_create_dst_escrow(&oversized, &src_cancellation_time, &0, &0), Err(Ok(Error::FeeTooHigh)));
+
+        let escrow_address = prepare_escrow(&s, &immutables);
+        assert_eq!(s.client.create_dst_escrow(&immutables, &src_cancellation_time, &0, &0), escrow_address);
+    }
+
+    #[test]
+    fn test_surplus_sharing() {
+        let s = setup();
+
+        let immutables = funded_immutables(&s);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        // Sharing a surplus needs somewhere to send it
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &900, &50);
+        assert_eq!(result, Err(Ok(Error::FeeReceiverNotSet)));
+
+        let protocol = Address::generate(&s.env);
+        s.client.set_protocol_fee_receiver(&protocol);
+        assert_eq!(s.client.get_protocol_fee_receiver(), Some(protocol.clone()));
+
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &900, &101);
+        assert_eq!(result, Err(Ok(Error::InvalidImmutables)));
+
+        // The escrow address commits to the surplus share the factory records
+        let mut recorded = immutables.clone();
+        recorded.estimated_amount = 900;
+        recorded.surplus_percentage = 50;
+        recorded.protocol_fee_receiver = Some(protocol.clone());
+        let escrow_address = prepare_escrow(&s, &recorded);
+        assert_eq!(s.client.create_dst_escrow(&immutables, &src_cancellation_time, &900, &50), escrow_address);
+
+        let escrow = escrow_dst::EscrowDstClient::new(&s.env, &escrow_address);
+        let stored = escrow.get_immutables();
+        assert_eq!(stored.estimated_amount, 900);
+        assert_eq!(stored.surplus_percentage, 50);
+        assert_eq!(stored.protocol_fee_receiver, Some(protocol));
+    }
+
+    #[test]
+    fn test_attested_surplus_share() {
+        let s = setup();
+        let immutables = funded_immutables(&s);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+        let [first, second, _] = set_committee(&s);
+        let protocol = Address::generate(&s.env);
+        s.client.set_protocol_fee_receiver(&protocol);
+
+        // The committee signs the immutables as stored, protocol fee receiver included
+        let attestation = attest_surplus(&s, &immutables, src_cancellation_time, 900, 50, &[&first, &second]);
+        s.client.set_protocol_fee_receiver(&Address::generate(&s.env));
+        let result = s.client.try_create_dst_escrow_attested(&immutables, &src_cancellation_time, &900, &50, &attestation);
+        assert_eq!(result, Err(Err(InvokeError::Abort)));
+
+        s.client.set_protocol_fee_receiver(&protocol);
+        let mut recorded = immutables.clone();
+        recorded.estimated_amount = 900;
+        recorded.surplus_percentage = 50;
+        recorded.protocol_fee_receiver = Some(protocol.clone());
+        let escrow_address = prepare_escrow(&s, &recorded);
+        let result = s.client.create_dst_escrow_attested(&immutables, &src_cancellation_time, &900, &50, &attestation);
+        assert_eq!(result, escrow_address);
+    }
+
+    #[test]
+    fn test_fill_fee() {
+        let s = setup();
+
+        let admin = Address::generate(&s.env);
+        let fee_bank_id = s.env.register(fee_bank::FeeBank, (admin, s.native_token.clone()));
+        let fee_bank = fee_bank::FeeBankClient::new(&s.env, &fee_bank_id);
+        fee_bank.set_charger(&s.client.address, &true);
+
+        s.client.set_fee_bank(&fee_bank_id, &10);
+        assert_eq!(s.client.get_fee_bank(), Some(fee_bank_id.clone()));
+        assert_eq!(s.client.get_fill_fee(), 10);
+        assert_eq!(s.client.try_set_fee_bank(&fee_bank_id, &-1), Err(Ok(Error::InvalidAmount)));
+
+        let immutables = funded_immutables(&s);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+        let escrow_address = prepare_escrow(&s, &immutables);
+
+        // The resolver has not pre-paid any fees yet
+        assert_eq!(s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0), Err(Ok(Error::InsufficientFeeBalance)));
+
+        StellarAssetClient::new(&s.env, &s.native_token).mint(&immutables.taker, &15);
+        fee_bank.deposit(&immutables.taker, &15);
+
+        assert_eq!(s.client.create_dst_escrow(&immutables, &src_cancellation_time, &0, &0), escrow_address);
+        assert_eq!(fee_bank.balance(&immutables.taker), 5);
+        assert_eq!(fee_bank.collected(), 10);
+    }
+
+    #[test]
+    fn test_create_dst_escrows() {
+        let s = setup();
+
+        // Two fills by the same taker
+        let first = funded_immutables(&s);
+        let mut second = funded_immutables(&s);
+        second.taker = first.taker.clone();
+        second.order_hash = BytesN::from_array(&s.env, &[9u8; 32]);
+        StellarAssetClient::new(&s.env, &second.token).mint(&second.taker, &second.amount);
+        let first_address = prepare_escrow(&s, &first);
+        let second_address = prepare_escrow(&s, &second);
+        let src_cancellation_time = first.deployed_at + 10800;
+
+        let batch = vec![
+            &s.env,
+            (first.clone(), src_cancellation_time, 0i128, 0u32),
+            (second.clone(), src_cancellation_time, 0i128, 0u32),
+        ];
+        let escrows = s.client.create_dst_escrows(&batch);
+        assert_eq!(escrows, vec![&s.env, first_address.clone(), second_address.clone()]);
+
+        // Each escrow of the batch gets its own creation event
+        let created = |immutables: &Immutables, escrow: &Address| (
+            (symbol_short!("created"), immutables.hashlock.clone()).into_val(&s.env),
+            (escrow.clone(), immutables.taker.clone()).into_val(&s.env),
+        );
+        let events = vec![&s.env, created(&first, &first_address), created(&second, &second_address)];
+        assert_eq!(factory_events(&s), events);
+
+        // The taker signs a single authorization covering the whole batch
+        let auths = s.env.auths();
+        assert_eq!(auths.len(), 1);
+        assert_eq!(auths[0].0, first.taker);
+        assert_eq!(auths[0].1.sub_invocations.len(), 2);
+
+        assert_eq!(TokenClient::new(&s.env, &first.token).balance(&first_address), 1000);
+        assert_eq!(TokenClient::new(&s.env, &second.token).balance(&second_address), 1000);
+    }
+
+    #[test]
+    fn test_create_dst_escrows_atomic() {
+        let s = setup();
+
+        let first = funded_immutables(&s);
+        let first_address = prepare_escrow(&s, &first);
+        let mut second = funded_immutables(&s);
+        second.order_hash = BytesN::from_array(&s.env, &[9u8; 32]);
+        let src_cancellation_time = first.deployed_at + 10800;
+
+        // The second escrow would outlive the source chain's cancellation
+        let batch = vec![
+            &s.env,
+            (first.clone(), src_cancellation_time, 0i128, 0u32),
+            (second, src_cancellation_time - 1, 0i128, 0u32),
+        ];
+        let result = s.client.try_create_dst_escrows(&batch);
+        assert_eq!(result, Err(Ok(Error::InvalidCreationTime)));
+
+        // The first escrow was rolled back with the rest of the batch, deployment included
+        assert_eq!(TokenClient::new(&s.env, &first.token).balance(&first.taker), 1000);
+        let escrow = escrow_dst::EscrowDstClient::new(&s.env, &first_address);
+        assert_eq!(escrow.try_get_state(), Err(Err(InvokeError::Abort)));
+    }
+
+    #[test]
+    fn test_registry() {
+        let s = setup();
+
+        // Two fills by the same taker for different makers
+        let first = funded_immutables(&s);
+        let mut second = funded_immutables(&s);
+        second.taker = first.taker.clone();
+        second.order_hash = BytesN::from_array(&s.env, &[9u8; 32]);
+        StellarAssetClient::new(&s.env, &second.token).mint(&second.taker, &second.amount);
+        prepare_escrow(&s, &first);
+        prepare_escrow(&s, &second);
+        let src_cancellation_time = first.deployed_at + 10800;
+
+        let first_address = s.client.create_dst_escrow(&first, &src_cancellation_time, &0, &0);
+        let second_address = s.client.create_dst_escrow(&second, &src_cancellation_time, &0, &0);
+
+        let page = s.client.escrows_by_taker(&first.taker, &0, &1);
+        assert_eq!(page.records.get(0).unwrap().escrow, first_address);
+        assert_eq!(page.next_cursor, Some(1));
+        let page = s.client.escrows_by_taker(&first.taker, &1, &1);
+        assert_eq!(page.records.get(0).unwrap().escrow, second_address);
+        assert_eq!(page.next_cursor, None);
+
+        let page = s.client.escrows_by_maker(&second.maker, &0, &10);
+        assert_eq!(page.records.len(), 1);
+        assert_eq!(page.records.get(0).unwrap().order_hash, second.order_hash);
+        assert_eq!(s.client.escrows_by_order(&first.order_hash, &0, &10).records.len(), 1);
+
+        // Registry entries are created live for the bump period
+        let ttl = s.env.as_contract(&s.client.address, || {
+            s.env.storage().persistent().get_ttl(&(symbol_short!("escrow"), first_address.clone()))
+        });
+        assert_eq!(ttl, REGISTRY_BUMP_AMOUNT);
+        s.client.extend_escrow_ttl(&first_address);
+        assert_eq!(s.client.try_extend_escrow_ttl(&Address::generate(&s.env)), Err(Ok(Error::InvalidAddress)));
+    }
+
+    /// Committee of three relayer keys, two of which must sign
+    fn set_committee(s: &Setup) -> [ed25519_dalek::SigningKey; 3] {
+        let keys = [1u8, 2, 3].map(|n| ed25519_dalek::SigningKey::from_bytes(&[n; 32]));
+        let mut relayers = Vec::new(&s.env);
+        for key in keys.iter() {
+            relayers.push_back(BytesN::from_array(&s.env, &key.verifying_key().to_bytes()));
+        }
+        s.client.set_attestation_committee(&relayers, &2);
+        keys
+    }
+
+    /// Attestation by `keys` of an escrow created with no surplus share
+    fn attest(
+        s: &Setup,
+        immutables: &Immutables,
+        src_cancellation_time: u64,
+        keys: &[&ed25519_dalek::SigningKey],
+    ) -> Attestation {
+        attest_surplus(s, immutables, src_cancellation_time, 0, 0, keys)
+    }
+
+    /// Attestation by `keys` of an escrow sharing `surplus_percentage` of the fill above the estimate
+    fn attest_surplus(
+        s: &Setup,
+        immutables: &Immutables,
+        src_cancellation_time: u64,
+        estimated_taking_amount: i128,
+        surplus_percentage: u32,
+        keys: &[&ed25519_dalek::SigningKey],
+    ) -> Attestation {
+        use ed25519_dalek::Signer;
+
+        let src_escrow = Bytes::from_array(&s.env, &[0xaa; 20]);
+        let digest = s.client.compute_attestation_digest(
+            &1,
+            &src_escrow,
+            immutables,
+            &src_cancellation_time,
+            &estimated_taking_amount,
+            &surplus_percentage,
+        );
+        let mut signatures = Vec::new(&s.env);
+        for key in keys {
+            signatures.push_back(RelayerSignature {
+                public_key: BytesN::from_array(&s.env, &key.verifying_key().to_bytes()),
+                signature: BytesN::from_array(&s.env, &key.sign(&digest.to_array()).to_bytes()),
+            });
+        }
+        Attestation { src_chain_id: 1, src_escrow, signatures }
+    }
+
+    #[test]
+    fn test_create_dst_escrow_attested() {
+        let s = setup();
+        let immutables = funded_immutables(&s);
+        let escrow_address = prepare_escrow(&s, &immutables);
+        let src_cancellation_time = immutables.deployed_at + 10800;
+
+        // Without a committee there is nothing to attest against
+        let attestation = attest(&s, &immutables, src_cancellation_time, &[]);
+        let result = s.client.try_create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Ok(Error::NotInitialized)));
+
+        let [first, second, third] = set_committee(&s);
+        assert_eq!(s.client.get_attestation_committee().unwrap().threshold, 2);
+
+        // Unattested escrows are refused once a committee is set
+        let result = s.client.try_create_dst_escrow(&immutables, &src_cancellation_time, &0, &0);
+        assert_eq!(result, Err(Ok(Error::AttestationRequired)));
+        let result = s.client.try_create_dst_escrows(&vec![&s.env, (immutables.clone(), src_cancellation_time, 0, 0)]);
+        assert_eq!(result, Err(Ok(Error::AttestationRequired)));
+
+        // One relayer is not enough, even signing twice
+        let attestation = attest(&s, &immutables, src_cancellation_time, &[&first]);
+        let result = s.client.try_create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Ok(Error::InsufficientAttestations)));
+        let attestation = attest(&s, &immutables, src_cancellation_time, &[&first, &first]);
+        let result = s.client.try_create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Ok(Error::InvalidSignature)));
+
+        // Keys outside the committee do not count
+        let outsider = ed25519_dalek::SigningKey::from_bytes(&[4u8; 32]);
+        let attestation = attest(&s, &immutables, src_cancellation_time, &[&first, &outsider]);
+        let result = s.client.try_create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Ok(Error::InvalidSignature)));
+
+        // An attestation of a source escrow on another chain than the order's
+        let mut attestation = attest(&s, &immutables, src_cancellation_time, &[&first, &third]);
+        attestation.src_chain_id = 2;
+        let result = s.client.try_create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Ok(Error::ChainIdMismatch)));
+
+        // Signatures over other arguments trap in the host: another source cancellation time...
+        let attestation = attest(&s, &immutables, src_cancellation_time + 1, &[&first, &third]);
+        let result = s.client.try_create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Err(InvokeError::Abort)));
+
+        // ...or another surplus estimate
+        let attestation = attest_surplus(&s, &immutables, src_cancellation_time, 900, 0, &[&first, &third]);
+        let result = s.client.try_create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Err(InvokeError::Abort)));
+
+        // ...or another receiver than the order's
+        let attestation = attest(&s, &immutables, src_cancellation_time, &[&first, &third]);
+        let mut redirected = immutables.clone();
+        redirected.receiver = Some(Address::generate(&s.env));
+        let result = s.client.try_create_dst_escrow_attested(&redirected, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Err(InvokeError::Abort)));
+
+        // Too few signers are reported before any signature is checked
+        let mut attestation = attest(&s, &immutables, src_cancellation_time, &[&first]);
+        let forged = RelayerSignature {
+            signature: BytesN::from_array(&s.env, &[0; 64]),
+            ..attestation.signatures.get(0).unwrap()
+        };
+        attestation.signatures.set(0, forged);
+        let result = s.client.try_create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, Err(Ok(Error::InsufficientAttestations)));
+
+        let attestation = attest(&s, &immutables, src_cancellation_time, &[&third, &second]);
+        let result = s.client.create_dst_escrow_attested(&immutables, &src_cancellation_time, &0, &0, &attestation);
+        assert_eq!(result, escrow_address);
+        let attested = (
+            (symbol_short!("attested"), attestation.src_escrow.clone()).into_val(&s.env),
+            (1u64, 2u32).into_val(&s.env),
+        );
+        let created = (
+            (symbol_short!("created"), immutables.hashlock.clone()).into_val(&s.env),
+            (escrow_address.clone(), immutables.taker.clone()).into_val(&s.env),
+        );
+        assert_eq!(factory_events(&s), vec![&s.env, attested, created]);
+        assert_eq!(TokenClient::new(&s.env, &immutables.token).balance(&escrow_address), 1000);
+    }
+
+    #[test]
+    fn test_attestation_committee() {
+        let s = setup();
+        let key = |n: u8| BytesN::from_array(&s.env, &[n; 32]);
+
+        // The threshold must be reachable by distinct relayers
+        let result = s.client.try_set_attestation_committee(&vec![&s.env, key(1), key(2)], &3);
+        assert_eq!(result, Err(Ok(Error::InvalidThreshold)));
+        let result = s.client.try_set_attestation_committee(&vec![&s.env, key(1), key(1)], &2);
+        assert_eq!(result, Err(Ok(Error::InvalidThreshold)));
+
+        s.client.set_attestation_committee(&vec![&s.env, key(1), key(2)], &1);
+        assert_eq!(
+            s.client.get_attestation_committee(),
+            Some(Committee { relayers: vec![&s.env, key(1), key(2)], threshold: 1 })
+        );
+
+        // A zero threshold turns attestations off again
+        s.client.set_attestation_committee(&Vec::new(&s.env), &0);
+        assert_eq!(s.client.get_attestation_committee(), None);
+
+        let immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
+        s.client.create_dst_escrow(&immutables, &(immutables.deployed_at + 10800), &0, &0);
+    }
+}
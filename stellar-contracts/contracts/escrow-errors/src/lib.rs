@@ -0,0 +1,69 @@
+//! Error codes of the escrow protocol contracts
+//!
+//! Every contract returns this one enum, so a code means the same thing whichever
+//! contract a resolver bot gets it from.
+#![no_std]
+#![allow(clippy::enum_variant_names)]
+use soroban_sdk::contracterror;
+
+/// Error codes shared by every contract of the protocol
+///
+/// Codes 4 (`InvalidCaller`) and 6 (`InvalidTime`) are retired and must not be reused.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    InvalidAddress = 3,
+    InvalidSecret = 5,
+    AlreadyWithdrawn = 7,
+    AlreadyCancelled = 8,
+    InsufficientBalance = 9,
+    TransferFailed = 10,
+    InvalidImmutables = 11,
+    TooEarlyForWithdrawal = 12,
+    TooEarlyForPublicWithdrawal = 13,
+    TooEarlyForCancellation = 14,
+    TooEarlyForPublicCancellation = 15,
+    WindowClosed = 16,
+    BalanceMismatch = 17,
+    NotFactory = 18,
+    InvalidCreationTime = 19,
+    EscrowCreationFailed = 20,
+    ClawbackNotAllowed = 21,
+    NothingToClaim = 22,
+    FeeTooHigh = 23,
+    FeeReceiverNotSet = 24,
+    InsufficientFeeBalance = 25,
+    NotFeeCharger = 26,
+    InvalidAmount = 27,
+    EscrowActive = 28,
+    EscrowNotEmpty = 29,
+    SecretNotRegistered = 30,
+    InvalidSignature = 31,
+    AuthNotAllowed = 32,
+    OrderLimitExceeded = 33,
+    DailyLimitExceeded = 34,
+    OrderNotFound = 35,
+    AttestationRequired = 36,
+    InsufficientAttestations = 37,
+    ChainIdMismatch = 38,
+    TimelockOutOfPolicy = 39,
+    OrderAlreadyFilled = 40,
+    InvalidThreshold = 41,
+    InvalidTimelockPreset = 42,
+    FinalizationFailed = 43,
+}
+
+/// Include the `Error` spec in the contract spec of the crate invoking it
+///
+/// The spec entry generated by `contracterror` only reaches a contract's wasm when it
+/// is defined in the contract's own crate, so each contract re-exports it here.
+#[macro_export]
+macro_rules! export_error_spec {
+    () => {
+        #[cfg_attr(target_family = "wasm", link_section = "contractspecv0")]
+        pub static __SPEC_XDR_TYPE_ERROR: [u8; $crate::Error::spec_xdr().len()] = $crate::Error::spec_xdr();
+    };
+}
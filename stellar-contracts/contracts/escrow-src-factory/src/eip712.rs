@@ -0,0 +1,217 @@
+//! EIP-712 hashing of 1inch Limit Order Protocol v4 orders, as signed by EVM makers
+
+use soroban_sdk::{contracttype, crypto::Hash, Bytes, BytesN, Env, U256};
+
+/// Type string of the EIP-712 domain
+const DOMAIN_TYPE: &[u8] = b"EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+
+/// Type string of the Limit Order Protocol `Order` struct
+const ORDER_TYPE: &[u8] = b"Order(uint256 salt,address maker,address receiver,address makerAsset,address takerAsset,uint256 makingAmount,uint256 takingAmount,uint256 makerTraits)";
+
+/// Name and version of the Limit Order Protocol v4 domain
+const DOMAIN_NAME: &[u8] = b"1inch Aggregation Router";
+const DOMAIN_VERSION: &[u8] = b"6";
+
+/// Limit Order Protocol order, field for field as in the EIP-712 `Order` struct
+#[contracttype]
+#[derive(Clone)]
+pub struct EvmOrder {
+    pub salt: U256,
+    pub maker: BytesN<20>,
+    pub receiver: BytesN<20>,       // Zero address if the maker receives the taking amount
+    pub maker_asset: BytesN<20>,
+    pub taker_asset: BytesN<20>,
+    pub making_amount: U256,
+    pub taking_amount: U256,
+    pub maker_traits: U256,
+}
+
+/// Domain separator of the Limit Order Protocol deployed at `verifying_contract` on `chain_id`
+pub fn domain_separator(env: &Env, chain_id: u64, verifying_contract: &BytesN<20>) -> BytesN<32> {
+    hash_domain(env, DOMAIN_NAME, DOMAIN_VERSION, chain_id, verifying_contract)
+}
+
+/// EIP-712 `hashStruct` of an order
+pub fn order_struct_hash(env: &Env, order: &EvmOrder) -> BytesN<32> {
+    let mut encoded = keccak(env, ORDER_TYPE);
+    append_uint(&mut encoded, &order.salt);
+    append_address(&mut encoded, &order.maker);
+    append_address(&mut encoded, &order.receiver);
+    append_address(&mut encoded, &order.maker_asset);
+    append_address(&mut encoded, &order.taker_asset);
+    append_uint(&mut encoded, &order.making_amount);
+    append_uint(&mut encoded, &order.taking_amount);
+    append_uint(&mut encoded, &order.maker_traits);
+    env.crypto().keccak256(&encoded).into()
+}
+
+/// Order hash the maker signs: keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(order))
+pub fn order_hash(env: &Env, domain_separator: &BytesN<32>, order: &EvmOrder) -> Hash<32> {
+    let mut message = Bytes::from_array(env, &[0x19, 0x01]);
+    message.append(&domain_separator.clone().into());
+    message.append(&order_struct_hash(env, order).into());
+    env.crypto().keccak256(&message)
+}
+
+fn hash_domain(env: &Env, name: &[u8], version: &[u8], chain_id: u64, verifying_contract: &BytesN<20>) -> BytesN<32> {
+    let mut encoded = keccak(env, DOMAIN_TYPE);
+    encoded.append(&keccak(env, name));
+    encoded.append(&keccak(env, version));
+    append_uint(&mut encoded, &U256::from_u128(env, chain_id as u128));
+    append_address(&mut encoded, verifying_contract);
+    env.crypto().keccak256(&encoded).into()
+}
+
+fn keccak(env: &Env, data: &[u8]) -> Bytes {
+    env.crypto().keccak256(&Bytes::from_slice(env, data)).into()
+}
+
+/// Append an address left-padded to 32 bytes
+fn append_address(encoded: &mut Bytes, address: &BytesN<20>) {
+    encoded.extend_from_array(&[0u8; 12]);
+    encoded.append(&address.clone().into());
+}
+
+/// Append a big-endian uint256
+fn append_uint(encoded: &mut Bytes, value: &U256) {
+    encoded.append(&value.to_be_bytes());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bytes<const N: usize>(env: &Env, hex: &str) -> BytesN<N> {
+        let mut array = [0u8; N];
+        for (i, byte) in array.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        BytesN::from_array(env, &array)
+    }
+
+    fn uint(env: &Env, hex: &str) -> U256 {
+        U256::from_be_bytes(env, &bytes::<32>(env, hex).into())
+    }
+
+    /// Limit Order Protocol v4 on Ethereum and BSC
+    const LOP: &str = "111111125421ca6dc452d289314280a0f8842a65";
+
+    alloy_sol_types::sol! {
+        /// The Limit Order Protocol `Order` struct, for alloy's reference EIP-712 encoding
+        struct Order {
+            uint256 salt;
+            address maker;
+            address receiver;
+            address makerAsset;
+            address takerAsset;
+            uint256 makingAmount;
+            uint256 takingAmount;
+            uint256 makerTraits;
+        }
+    }
+
+    /// Order for 100 USDC on Ethereum against 99 USDC on BSC, shaped like jsclient/order-creation.js
+    ///
+    /// Fields laid out as `@1inch/cross-chain-sdk` 0.1.15 builds them (the version pinned in
+    /// jsclient/package-lock.json), but not yet printed by it: the registry was unreachable
+    /// where this vector was written, so the expected hashes are alloy's EIP-712 encoding of
+    /// these fields. Re-pin the fields and hashes from jsclient/order-hash-vectors.js.
+    fn order(env: &Env) -> EvmOrder {
+        EvmOrder {
+            salt: uint(env, "0000000000000000000001f4c1b2a33e1d3ba35fd9a5ae8457f3df01e4a3c17a"),
+            maker: bytes(env, "2222222222222222222222222222222222222222"),
+            receiver: bytes(env, "0000000000000000000000000000000000000000"),
+            maker_asset: bytes(env, "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"),
+            // Cross-chain orders name a placeholder taker asset on the source chain
+            taker_asset: bytes(env, "da0000d4000015a526378bb6fafc650cea5966f8"),
+            making_amount: U256::from_u32(env, 100_000_000),
+            taking_amount: U256::from_u32(env, 99_000_000),
+            // No partial fills, post-interaction, has extension; nonce 0x6502b1a4c7 and
+            // expiration 132, the auction's start and duration plus the SDK's 12s delay
+            maker_traits: uint(env, "8a00000000000000000000006502b1a4c7000000008400000000000000000000"),
+        }
+    }
+
+    /// Hash of `order` under the domain of the Limit Order Protocol at `lop` on `chain_id`, per alloy
+    fn alloy_order_hash(order: &EvmOrder, chain_id: u64, lop: &BytesN<20>) -> [u8; 32] {
+        use alloy_primitives::{Address, U256 as Uint};
+        use alloy_sol_types::SolStruct;
+
+        let address = |address: &BytesN<20>| Address::from(address.to_array());
+        let uint = |value: &U256| Uint::from_be_bytes(bytes32(&value.to_be_bytes()));
+        let domain = alloy_sol_types::Eip712Domain::new(
+            Some("1inch Aggregation Router".into()),
+            Some("6".into()),
+            Some(Uint::from(chain_id)),
+            Some(address(lop)),
+            None,
+        );
+        let order = Order {
+            salt: uint(&order.salt),
+            maker: address(&order.maker),
+            receiver: address(&order.receiver),
+            makerAsset: address(&order.maker_asset),
+            takerAsset: address(&order.taker_asset),
+            makingAmount: uint(&order.making_amount),
+            takingAmount: uint(&order.taking_amount),
+            makerTraits: uint(&order.maker_traits),
+        };
+        order.eip712_signing_hash(&domain).0
+    }
+
+    fn bytes32(bytes: &Bytes) -> [u8; 32] {
+        let mut array = [0u8; 32];
+        bytes.copy_into_slice(&mut array);
+        array
+    }
+
+    #[test]
+    fn test_eip712_example_domain() {
+        // Domain of the `Mail` example in the EIP-712 specification
+        let env = Env::default();
+        let contract = bytes(&env, "cccccccccccccccccccccccccccccccccccccccc");
+        assert_eq!(
+            hash_domain(&env, b"Ether Mail", b"1", 1, &contract),
+            bytes(&env, "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f")
+        );
+    }
+
+    #[test]
+    fn test_order_type_hash() {
+        let env = Env::default();
+        assert_eq!(
+            keccak(&env, ORDER_TYPE),
+            Bytes::from(bytes::<32>(&env, "3af21ec5a20011b88d3b7b4ed7c806cef05a5980cf34974bcd53566a131f7e4c"))
+        );
+    }
+
+    #[test]
+    fn test_order_hash() {
+        let env = Env::default();
+        let lop = bytes(&env, LOP);
+        let order = order(&env);
+
+        assert_eq!(
+            order_struct_hash(&env, &order),
+            bytes(&env, "29b2964ee66c2ed57fa8af70186351a62fe0edb41a2f1e2f03e8bfa4833c6559")
+        );
+
+        let ethereum = domain_separator(&env, 1, &lop);
+        assert_eq!(ethereum, bytes(&env, "d999e213f11c7bfa3e796c3409e316f25e02aa3e25e5c207a92e381c7d22b6de"));
+        assert_eq!(
+            order_hash(&env, &ethereum, &order).to_bytes(),
+            bytes(&env, "e59f8f2d75cfd38cb09b5f201f3d6a0a6f90118ecb124b6f0c40ed350c92be92")
+        );
+
+        // The same order signed for another chain has another hash
+        let bsc = domain_separator(&env, 56, &lop);
+        assert_eq!(
+            order_hash(&env, &bsc, &order).to_bytes(),
+            bytes(&env, "33a5f8ea422b00546f8d2e8780cbff20ac0c15abbdd6b50d56ae4746668f5dfc")
+        );
+
+        // alloy's EIP-712 implementation agrees on both chains
+        assert_eq!(order_hash(&env, &ethereum, &order).to_array(), alloy_order_hash(&order, 1, &lop));
+        assert_eq!(order_hash(&env, &bsc, &order).to_array(), alloy_order_hash(&order, 56, &lop));
+    }
+}
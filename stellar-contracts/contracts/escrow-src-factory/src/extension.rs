@@ -0,0 +1,166 @@
+//! Cross-chain escrow terms a 1inch Limit Order Protocol v4 order commits to in its extension
+
+use soroban_sdk::{xdr::ToXdr, Address, Bytes, BytesN, Env};
+
+use crate::eip712::EvmOrder;
+
+/// `HAS_EXTENSION` flag of the maker traits (bit 249)
+const HAS_EXTENSION_FLAG: u8 = 0x02;
+
+/// Index of the post-interaction data among the extension's dynamic fields
+const POST_INTERACTION_FIELD: u32 = 7;
+
+/// Length of the escrow factory address prefixing the post-interaction data
+const TARGET_LENGTH: u32 = 20;
+
+/// Length of the escrow arguments ending the post-interaction data
+/// (hashlock info, destination chain, destination token, deposits, timelocks)
+const ESCROW_ARGS_LENGTH: u32 = 5 * 32;
+
+/// Escrow arguments of the source escrow factory's post-interaction
+pub struct EscrowArgs {
+    pub hashlock_info: BytesN<32>,     // Hashlock, or Merkle root of the hashlocks for multiple fills
+    pub dst_chain_id: u64,
+    pub src_safety_deposit: u128,      // High half of the deposits word; the low half is the destination's
+    pub timelocks: [u32; 7],           // Stage offsets, SrcWithdrawal to DstCancellation
+}
+
+/// Check that the order commits to `extension`, as the Limit Order Protocol does
+///
+/// The order must flag that it has an extension, and the low 160 bits of its salt
+/// must be the low 160 bits of the extension's keccak256 hash.
+pub fn commits_to(env: &Env, order: &EvmOrder, extension: &Bytes) -> bool {
+    let traits = order.maker_traits.to_be_bytes();
+    if traits.get(0).unwrap_or(0) & HAS_EXTENSION_FLAG == 0 {
+        return false;
+    }
+
+    let hash = env.crypto().keccak256(extension).to_array();
+    let mut salt = [0u8; 32];
+    order.salt.to_be_bytes().copy_into_slice(&mut salt);
+    salt[12..] == hash[12..]
+}
+
+/// Parse the escrow arguments from the post-interaction data of an extension
+///
+/// The extension starts with a word of cumulative end offsets, the one of field `i`
+/// in bits `32 * i` to `32 * i + 31`. Returns `None` if the extension is malformed
+/// or names a destination chain ID beyond 64 bits.
+pub fn escrow_args(extension: &Bytes) -> Option<EscrowArgs> {
+    if extension.len() < 32 {
+        return None;
+    }
+    let offsets = word(extension, 0);
+    let begin = 32 + offset(&offsets, POST_INTERACTION_FIELD - 1);
+    let end = 32 + offset(&offsets, POST_INTERACTION_FIELD);
+    if begin > end || end > extension.len() || end - begin < TARGET_LENGTH + ESCROW_ARGS_LENGTH {
+        return None;
+    }
+
+    let args = end - ESCROW_ARGS_LENGTH;
+    let dst_chain_id = word(extension, args + 32);
+    if dst_chain_id[..24].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    let deposits = word(extension, args + 96);
+    let packed_timelocks = word(extension, args + 128);
+    let mut timelocks = [0u32; 7];
+    for (stage, timelock) in timelocks.iter_mut().enumerate() {
+        let at = 28 - 4 * stage;
+        *timelock = u32::from_be_bytes(packed_timelocks[at..at + 4].try_into().unwrap());
+    }
+
+    Some(EscrowArgs {
+        hashlock_info: BytesN::from_array(extension.env(), &word(extension, args)),
+        dst_chain_id: u64::from_be_bytes(dst_chain_id[24..].try_into().unwrap()),
+        src_safety_deposit: u128::from_be_bytes(deposits[..16].try_into().unwrap()),
+        timelocks,
+    })
+}
+
+/// Address a Stellar token appears under in an EVM order: the last 20 bytes of its contract ID
+pub fn evm_asset(env: &Env, token: &Address) -> BytesN<20> {
+    // The XDR of a contract address ends with the 32-byte contract ID
+    let xdr = token.clone().to_xdr(env);
+    let mut asset = [0u8; 20];
+    xdr.slice(xdr.len() - 20..).copy_into_slice(&mut asset);
+    BytesN::from_array(env, &asset)
+}
+
+/// Read the big-endian word at byte `at`
+fn word(bytes: &Bytes, at: u32) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    bytes.slice(at..at + 32).copy_into_slice(&mut word);
+    word
+}
+
+/// End offset of dynamic field `field` relative to the end of the offsets word
+fn offset(offsets: &[u8; 32], field: u32) -> u32 {
+    let at = 28 - 4 * field as usize;
+    u32::from_be_bytes(offsets[at..at + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Extension with making amount data (field 2), post-interaction data (field 7) and custom data
+    fn extension(env: &Env) -> Bytes {
+        let mut args = [0u8; 160];
+        args[..32].fill(0x02);
+        args[63] = 56;
+        args[76..96].fill(0xdd);
+        args[111] = 7;
+        args[127] = 9;
+        for stage in 0..7u32 {
+            let at = 156 - 4 * stage as usize;
+            args[at..at + 4].copy_from_slice(&(100 * (stage + 1)).to_be_bytes());
+        }
+
+        // Fields 0 and 1 end at 0, 2 to 6 at 10, and 7 at 10 + 20 + 4 + 160
+        let mut offsets = [0u8; 32];
+        offsets[..4].copy_from_slice(&194u32.to_be_bytes());
+        for field in 2..7 {
+            offsets[28 - 4 * field..32 - 4 * field].copy_from_slice(&10u32.to_be_bytes());
+        }
+
+        let mut extension = Bytes::from_array(env, &offsets);
+        extension.extend_from_array(&[0xaa; 10]);
+        extension.extend_from_array(&[0x33; 20]);
+        // Fee data of the EVM factory preceding the escrow arguments
+        extension.extend_from_array(&[0xee; 4]);
+        extension.extend_from_array(&args);
+        extension.extend_from_array(&[0xcc; 8]);
+        extension
+    }
+
+    #[test]
+    fn test_escrow_args() {
+        let env = Env::default();
+        let args = escrow_args(&extension(&env)).unwrap();
+
+        assert_eq!(args.hashlock_info, BytesN::from_array(&env, &[0x02; 32]));
+        assert_eq!(args.dst_chain_id, 56);
+        assert_eq!(args.src_safety_deposit, 7);
+        assert_eq!(args.timelocks, [100, 200, 300, 400, 500, 600, 700]);
+    }
+
+    #[test]
+    fn test_malformed_extension() {
+        let env = Env::default();
+        let extension = extension(&env);
+
+        // Truncated before the end of the post-interaction data
+        assert!(escrow_args(&extension.slice(..220)).is_none());
+
+        // Post-interaction data too short for the escrow arguments
+        let mut short = extension.clone();
+        short.set(7, 30);
+        assert!(escrow_args(&short).is_none());
+
+        // Destination chain ID beyond 64 bits
+        let mut wide = extension.clone();
+        wide.set(32 + 10 + 20 + 4 + 32 + 23, 1);
+        assert!(escrow_args(&wide).is_none());
+    }
+}
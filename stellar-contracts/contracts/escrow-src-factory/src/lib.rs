@@ -1,10 +1,63 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, contracterror,
-    Address, BytesN, Env, symbol_short,
-    log
+    contract, contractclient, contractimpl, contracttype,
+    crypto::Hash, xdr::ToXdr, Address, Bytes, BytesN, Env, symbol_short,
+    token, Vec, U256
 };
 
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+mod eip712;
+mod extension;
+
+pub use eip712::EvmOrder;
+
+/// Maximum number of fee legs an escrow pays out (same as EscrowSrc)
+pub const MAX_FEE_LEGS: u32 = 4;
+
+/// Denominator for fees expressed in basis points
+const BPS_DENOMINATOR: i128 = 10_000;
+
+/// Approximate number of ledgers closed per day
+const DAY_IN_LEDGERS: u32 = 17_280;
+
+/// Nominal ledger close time, for sequence-based timelocks checked against durations in seconds
+const LEDGER_SECONDS: u64 = 5;
+
+/// Registry entries are extended to live this many ledgers ahead...
+const REGISTRY_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+
+/// ...whenever they are touched with less than this left
+const REGISTRY_LIFETIME_THRESHOLD: u32 = REGISTRY_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+/// Maximum number of records returned by a single registry page
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// Size of a fee leg, either fixed or in basis points of the escrowed amount (same as EscrowSrc)
+#[contracttype]
+#[derive(Clone)]
+pub enum FeeAmount {
+    Fixed(i128),
+    Bps(u32),
+}
+
+/// Part of the withdrawn tokens paid to a protocol or integrator (same as EscrowSrc)
+#[contracttype]
+#[derive(Clone)]
+pub struct FeeLeg {
+    pub recipient: Address,
+    pub amount: FeeAmount,
+}
+
+/// Clock the timelocks of an escrow count in (same as EscrowSrc)
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeBase {
+    Timestamp,                          // Seconds of ledger close time
+    Sequence,                           // Ledger sequence numbers
+}
+
 /// Immutable parameters for the escrow (same as EscrowSrc)
 #[contracttype]
 #[derive(Clone)]
@@ -16,24 +69,94 @@ pub struct Immutables {
     pub token: Address,
     pub amount: i128,
     pub safety_deposit: i128,
+    pub src_chain_id: u64,              // Chain the maker's tokens are locked on
+    pub dst_chain_id: u64,              // Chain the taker's tokens are paid out on
+    pub time_base: TimeBase,            // Clock the timelocks and deployed_at count in
     pub deployed_at: u64,
-    // Timelock durations in seconds from deployment (source-specific)
+    // Timelock durations from deployment, in seconds or ledgers per time_base
     pub src_withdrawal_start: u32,      // When taker can withdraw
     pub src_public_withdrawal_start: u32, // When anyone can withdraw for taker
     pub src_cancellation_start: u32,     // When taker can cancel
     pub src_public_cancellation_start: u32, // When anyone can cancel
+    pub dst_withdrawal_start: u32,      // When taker can withdraw
+    pub dst_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub dst_cancellation_start: u32,     // When taker can cancel
+    pub allow_clawback: bool,           // Order accepts tokens the issuer can claw back
+    pub fees: Vec<FeeLeg>,              // Paid out of the amount on withdrawal, at most MAX_FEE_LEGS
 }
 
-/// Error codes for the factory
-#[contracterror]
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
-#[repr(u32)]
-pub enum Error {
-    InsufficientEscrowBalance = 1,
-    InvalidCreationTime = 2,
-    TransferFailed = 3,
-    InvalidImmutables = 4,
-    EscrowCreationFailed = 5,
+/// Timelock policy for orders with one counterparty chain
+///
+/// Durations are in seconds; sequence-based timelocks count ledgers at the nominal close time.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TimelockPreset {
+    pub min_finality_lock: u32,         // Shortest wait before either taker can withdraw
+    pub min_private_withdrawal: u32,    // Shortest taker-only withdrawal window on either chain
+    pub min_private_cancellation: u32,  // Shortest taker-only cancellation window on the source chain
+    pub max_duration: u32,              // Latest start of the last stage on either chain
+}
+
+/// Registry record of an escrow created by this factory
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowRecord {
+    pub escrow: Address,
+    pub order_hash: BytesN<32>,
+    pub maker: Address,
+    pub taker: Address,
+    pub created_at: u64,
+    // Positions of the escrow in the order, maker and taker indices
+    pub order_index: u32,
+    pub maker_index: u32,
+    pub taker_index: u32,
+}
+
+/// Page of registry records and the cursor to continue from, if any are left
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowPage {
+    pub records: Vec<EscrowRecord>,
+    pub next_cursor: Option<u32>,
+}
+
+/// Registry index an escrow is listed under
+#[contracttype]
+#[derive(Clone)]
+pub enum RegistryIndex {
+    Order(BytesN<32>),
+    Maker(Address),
+    Taker(Address),
+}
+
+/// Interface of the EscrowSrc contracts deployed by this factory
+#[contractclient(name = "EscrowSrcClient")]
+pub trait EscrowSrcInterface {
+    fn init(
+        env: Env,
+        deployer: Address,
+        salt: BytesN<32>,
+        immutables: Immutables,
+        native_token: Address,
+    ) -> Result<(), Error>;
+    fn finalize(env: Env) -> Result<(), Error>;
+}
+
+/// Interface of the FeeBank charging resolvers for each fill
+#[contractclient(name = "FeeBankClient")]
+pub trait FeeBankInterface {
+    fn charge(env: Env, charger: Address, resolver: Address, amount: i128) -> Result<i128, Error>;
+}
+
+/// Where the tokens of a new source escrow come from
+#[derive(Clone, Copy, PartialEq)]
+enum Funding {
+    /// Transferred from the maker under its authorization
+    Transfer,
+    /// Pulled from the allowance the maker gave the factory
+    Allowance,
+    /// Already sent to the escrow address by the order protocol
+    Prefunded,
 }
 
 #[contract]
@@ -41,6 +164,20 @@ pub struct EscrowSrcFactory;
 
 #[contractimpl]
 impl EscrowSrcFactory {
+    /// Configure the factory with its admin, the EscrowSrc wasm, the native XLM asset contract
+    /// and the chain ID orders name for this network
+    pub fn __constructor(env: Env, admin: Address, escrow_wasm_hash: BytesN<32>, native_token: Address, chain_id: u64) {
+        env.storage().instance().set(&symbol_short!("admin"), &admin);
+        env.storage().instance().set(&symbol_short!("wasm"), &escrow_wasm_hash);
+        env.storage().instance().set(&symbol_short!("native"), &native_token);
+        env.storage().instance().set(&symbol_short!("chain"), &chain_id);
+    }
+
+    /// Get the chain ID orders must name as their source chain
+    pub fn get_chain_id(env: Env) -> u64 {
+        env.storage().instance().get(&symbol_short!("chain")).unwrap()
+    }
+
     /// Create a new source escrow contract
     /// This function maps the createSrcEscrow functionality from BaseEscrowFactory
     pub fn createsrc(
@@ -49,33 +186,294 @@ impl EscrowSrcFactory {
     ) -> Result<Address, Error> {
         // Validate the caller is the maker
         src_immutables.maker.require_auth();
+        // The taker pays the fill fee, so it has to consent as well
+        src_immutables.taker.require_auth();
 
-        // Create salt from immutables hash
-        let salt = Self::compute_salt(&env, &src_immutables);
+        Self::create_escrow(&env, src_immutables, Funding::Transfer)
+    }
 
-        // Compute the escrow address
-        let escrow_address = Self::compute_escrow_address(env.clone(), src_immutables.clone());
+    /// Create several source escrows at once, atomically
+    ///
+    /// Every escrow is deployed, funded and initialized as in `createsrc`. Each maker and
+    /// taker authorizes the batch once; if any escrow fails the whole batch is reverted.
+    pub fn createsrcs(env: Env, src_immutables: Vec<Immutables>) -> Result<Vec<Address>, Error> {
+        let mut escrows = Vec::new(&env);
+        let mut authorized: Vec<Address> = Vec::new(&env);
+        for immutables in src_immutables.iter() {
+            // Validate the maker and the taker paying the fill fee, once per address
+            for party in [&immutables.maker, &immutables.taker] {
+                if !authorized.contains(party) {
+                    party.require_auth();
+                    authorized.push_back(party.clone());
+                }
+            }
 
-        // Note: In Soroban, token transfers and native XLM transfers work differently than Ethereum
-        // The maker would need to:
-        // 1. Authorize token transfers to the escrow
-        // 2. Send native XLM to the escrow address
-        // 3. The factory then deploys and initializes the escrow
-        
-        // Log the requirements for the maker
-        log!(&env, "EscrowCreationRequirements", 
-              escrow_address, 
-              src_immutables.safety_deposit, 
-              src_immutables.token, 
-              src_immutables.amount);
+            escrows.push_back(Self::create_escrow(&env, immutables, Funding::Transfer)?);
+        }
+        Ok(escrows)
+    }
 
-        // Initialize the escrow with the immutables
-        Self::init_escrow(&env, &escrow_address, &salt, &src_immutables)?;
+    /// Create a source escrow for a Limit Order Protocol order an EVM maker signed (taker only)
+    ///
+    /// The order hash is recomputed from the order fields under the configured EIP-712
+    /// domain and must match `order_hash`, as must the making amount. The rest of the
+    /// immutables must match the order and the extension its salt commits to. The order's
+    /// maker must have signed it and be bound to the Stellar maker, whose tokens are pulled
+    /// from the allowance it gave this factory. Each order funds a single escrow.
+    pub fn createsrc_signed(
+        env: Env,
+        src_immutables: Immutables,
+        order: EvmOrder,
+        extension: Bytes,
+        signature: BytesN<64>,
+        recovery_id: u32,
+    ) -> Result<Address, Error> {
+        // The taker pays the fill fee, so it has to submit the order itself
+        src_immutables.taker.require_auth();
 
-        // Log the creation event
-        log!(&env, "SrcEscrowCreated", escrow_address, src_immutables.hashlock, src_immutables.maker);
+        if Self::is_order_filled(env.clone(), src_immutables.order_hash.clone()) {
+            return Err(Error::OrderAlreadyFilled);
+        }
 
-        Ok(escrow_address)
+        let domain_separator = Self::get_eip712_domain(env.clone()).ok_or(Error::NotInitialized)?;
+
+        let digest = eip712::order_hash(&env, &domain_separator, &order);
+        if digest.to_bytes() != src_immutables.order_hash
+            || src_immutables.amount <= 0
+            || order.making_amount != U256::from_u128(&env, src_immutables.amount as u128)
+        {
+            return Err(Error::InvalidImmutables);
+        }
+        Self::require_signed_terms(&env, &src_immutables, &order, &extension)?;
+
+        let signer = Self::recover_evm_address(&env, &digest, &signature, recovery_id);
+        if signer != order.maker {
+            return Err(Error::InvalidSignature);
+        }
+        match Self::get_evm_binding(env.clone(), signer) {
+            Some(maker) if maker == src_immutables.maker => {}
+            _ => return Err(Error::InvalidSignature),
+        }
+
+        let key = (symbol_short!("filled"), src_immutables.order_hash.clone());
+        let escrow = Self::create_escrow(&env, src_immutables, Funding::Allowance)?;
+        env.storage().persistent().set(&key, &true);
+        env.storage().persistent().extend_ttl(&key, REGISTRY_LIFETIME_THRESHOLD, REGISTRY_BUMP_AMOUNT);
+
+        Ok(escrow)
+    }
+
+    /// Check whether a signed order has already funded an escrow
+    pub fn is_order_filled(env: Env, order_hash: BytesN<32>) -> bool {
+        env.storage().persistent().has(&(symbol_short!("filled"), order_hash))
+    }
+
+    /// Create a source escrow for an order fill, as the post-interaction of the order protocol
+    ///
+    /// Only the configured order protocol can call this, after transferring the maker's
+    /// tokens and the resolver's safety deposit to the escrow address itself. The protocol
+    /// has the resolver, who is the taker, authorize the fill.
+    pub fn post_interaction(env: Env, src_immutables: Immutables) -> Result<Address, Error> {
+        let protocol = Self::get_order_protocol(env.clone()).ok_or(Error::NotInitialized)?;
+        protocol.require_auth();
+
+        Self::create_escrow(&env, src_immutables, Funding::Prefunded)
+    }
+
+    /// Set the order protocol allowed to create escrows in its post-interaction (admin only)
+    pub fn set_order_protocol(env: Env, protocol: Address) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        env.storage().instance().set(&symbol_short!("protocol"), &protocol);
+    }
+
+    /// Get the order protocol, if configured
+    pub fn get_order_protocol(env: Env) -> Option<Address> {
+        env.storage().instance().get(&symbol_short!("protocol"))
+    }
+
+    /// Bind an EVM address to the maker account funding the orders it signs (maker only)
+    ///
+    /// An EVM address is bound to at most one maker until that maker unbinds it.
+    pub fn bind_evm_address(env: Env, maker: Address, evm_address: BytesN<20>) -> Result<(), Error> {
+        maker.require_auth();
+
+        let key = (symbol_short!("evmbind"), evm_address.clone());
+        match env.storage().persistent().get::<_, Address>(&key) {
+            Some(bound) if bound != maker => return Err(Error::InvalidAddress),
+            _ => {}
+        }
+        env.storage().persistent().set(&key, &maker);
+
+        env.events().publish((symbol_short!("evm_bound"), maker), evm_address);
+
+        Ok(())
+    }
+
+    /// Remove the binding of an EVM address (bound maker only)
+    pub fn unbind_evm_address(env: Env, maker: Address, evm_address: BytesN<20>) -> Result<(), Error> {
+        maker.require_auth();
+
+        if Self::get_evm_binding(env.clone(), evm_address.clone()) != Some(maker) {
+            return Err(Error::InvalidAddress);
+        }
+        env.storage().persistent().remove(&(symbol_short!("evmbind"), evm_address));
+        Ok(())
+    }
+
+    /// Get the maker an EVM address is bound to, if any
+    pub fn get_evm_binding(env: Env, evm_address: BytesN<20>) -> Option<Address> {
+        env.storage().persistent().get(&(symbol_short!("evmbind"), evm_address))
+    }
+
+    /// Set the EIP-712 domain of the Limit Order Protocol EVM makers sign orders for (admin only)
+    pub fn set_eip712_domain(env: Env, chain_id: u64, verifying_contract: BytesN<20>) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        let domain_separator = eip712::domain_separator(&env, chain_id, &verifying_contract);
+        env.storage().instance().set(&symbol_short!("eip712"), &domain_separator);
+    }
+
+    /// Get the EIP-712 domain separator, if configured
+    pub fn get_eip712_domain(env: Env) -> Option<BytesN<32>> {
+        env.storage().instance().get(&symbol_short!("eip712"))
+    }
+
+    /// Mark a token whose issuer can claw back balances (admin only)
+    ///
+    /// The Stellar Asset Contract does not expose issuer flags, so clawback-enabled
+    /// assets have to be registered here to be refused by `createsrc`.
+    pub fn set_clawback_token(env: Env, token: Address, clawback_enabled: bool) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        let key = (symbol_short!("clawback"), token);
+        if clawback_enabled {
+            env.storage().persistent().set(&key, &true);
+        } else {
+            env.storage().persistent().remove(&key);
+        }
+    }
+
+    /// Check whether a token is registered as clawback-enabled
+    pub fn is_clawback_token(env: Env, token: Address) -> bool {
+        env.storage().persistent().has(&(symbol_short!("clawback"), token))
+    }
+
+    /// Set the timelock policy for orders with a counterparty chain (admin only)
+    ///
+    /// Orders with a chain that has no preset are not checked. A preset whose minimum
+    /// windows don't fit within its maximum duration is refused with `InvalidTimelockPreset`.
+    pub fn set_timelock_preset(env: Env, chain_id: u64, preset: TimelockPreset) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        let min_duration = preset.min_finality_lock as u64
+            + preset.min_private_withdrawal as u64
+            + preset.min_private_cancellation as u64;
+        if min_duration > preset.max_duration as u64 {
+            return Err(Error::InvalidTimelockPreset);
+        }
+        env.storage().persistent().set(&(symbol_short!("preset"), chain_id), &preset);
+        Ok(())
+    }
+
+    /// Remove the timelock policy for orders with a counterparty chain (admin only)
+    pub fn remove_timelock_preset(env: Env, chain_id: u64) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        env.storage().persistent().remove(&(symbol_short!("preset"), chain_id));
+    }
+
+    /// Get the timelock policy for orders with a counterparty chain, if set
+    pub fn get_timelock_preset(env: Env, chain_id: u64) -> Option<TimelockPreset> {
+        env.storage().persistent().get(&(symbol_short!("preset"), chain_id))
+    }
+
+    /// Set the maximum total fee, in basis points of the amount, an escrow may pay out (admin only)
+    pub fn set_max_fee_bps(env: Env, max_fee_bps: u32) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        env.storage().instance().set(&symbol_short!("maxfee"), &max_fee_bps);
+    }
+
+    /// Get the maximum total fee in basis points (no fees are allowed until configured)
+    pub fn get_max_fee_bps(env: Env) -> u32 {
+        env.storage().instance().get(&symbol_short!("maxfee")).unwrap_or(0)
+    }
+
+    /// Charge the taker (resolver) `fill_fee` from its FeeBank balance on every fill (admin only)
+    pub fn set_fee_bank(env: Env, fee_bank: Address, fill_fee: i128) -> Result<(), Error> {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        if fill_fee < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        env.storage().instance().set(&symbol_short!("feebank"), &fee_bank);
+        env.storage().instance().set(&symbol_short!("fillfee"), &fill_fee);
+        Ok(())
+    }
+
+    /// Get the FeeBank resolvers are charged from, if configured
+    pub fn get_fee_bank(env: Env) -> Option<Address> {
+        env.storage().instance().get(&symbol_short!("feebank"))
+    }
+
+    /// Get the fee charged per fill
+    pub fn get_fill_fee(env: Env) -> i128 {
+        env.storage().instance().get(&symbol_short!("fillfee")).unwrap_or(0)
+    }
+
+    /// Get the registry record of an escrow created by this factory
+    pub fn get_escrow(env: Env, escrow: Address) -> Option<EscrowRecord> {
+        env.storage().persistent().get(&(symbol_short!("escrow"), escrow))
+    }
+
+    /// List the escrows created for an order, `limit` at a time starting at `cursor`
+    pub fn escrows_by_order(env: Env, order_hash: BytesN<32>, cursor: u32, limit: u32) -> EscrowPage {
+        Self::page(&env, RegistryIndex::Order(order_hash), cursor, limit)
+    }
+
+    /// List the escrows created for a maker, `limit` at a time starting at `cursor`
+    pub fn escrows_by_maker(env: Env, maker: Address, cursor: u32, limit: u32) -> EscrowPage {
+        Self::page(&env, RegistryIndex::Maker(maker), cursor, limit)
+    }
+
+    /// List the escrows created for a taker, `limit` at a time starting at `cursor`
+    pub fn escrows_by_taker(env: Env, taker: Address, cursor: u32, limit: u32) -> EscrowPage {
+        Self::page(&env, RegistryIndex::Taker(taker), cursor, limit)
+    }
+
+    /// Keep an escrow's registry entries live (anyone can pay for the extension)
+    pub fn extend_escrow_ttl(env: Env, escrow: Address) -> Result<(), Error> {
+        let record = Self::get_escrow(env.clone(), escrow).ok_or(Error::InvalidAddress)?;
+        Self::extend_record_ttl(&env, &record);
+        Ok(())
+    }
+
+    /// Finalize a withdrawn or cancelled escrow and drop it from the registry
+    ///
+    /// Anyone can pay for the cleanup; the escrow checks it is settled and empty.
+    pub fn finalize_escrow(env: Env, escrow: Address) -> Result<(), Error> {
+        let record = Self::get_escrow(env.clone(), escrow.clone()).ok_or(Error::InvalidAddress)?;
+
+        // Escrow errors share the factory's codes and are passed through as-is
+        match EscrowSrcClient::new(&env, &escrow).try_finalize() {
+            Ok(Ok(())) => {}
+            Err(Ok(error)) => return Err(error),
+            _ => return Err(Error::FinalizationFailed),
+        }
+
+        Self::remove_record(&env, &record);
+
+        env.events().publish((symbol_short!("finalize"),), escrow);
+
+        Ok(())
     }
 
     /// Compute the deterministic address for an escrow
@@ -89,15 +487,333 @@ impl EscrowSrcFactory {
     }
 
     /// Compute salt from immutables (similar to hashMem in Ethereum)
+    ///
+    /// The salt is the SHA-256 of the network ID and the XDR of the immutables, so every
+    /// term of the order, chain IDs included, decides the address, and the same order lands
+    /// at different addresses on testnet and mainnet. `deployed_at` is zeroed as the escrow
+    /// sets it on initialization.
     pub(crate) fn compute_salt(env: &Env, immutables: &Immutables) -> BytesN<32> {
-        // Create a deterministic salt from key immutables
-        let mut salt_array = [0u8; 32];
-        
-        // Use order_hash and hashlock for deterministic salt
-        salt_array[..16].copy_from_slice(&immutables.order_hash.to_array()[..16]);
-        salt_array[16..].copy_from_slice(&immutables.hashlock.to_array()[..16]);
-        
-        BytesN::from_array(env, &salt_array)
+        let mut immutables = immutables.clone();
+        immutables.deployed_at = 0;
+
+        let mut data = Bytes::from_array(env, &env.ledger().network_id().to_array());
+        data.append(&immutables.to_xdr(env));
+        env.crypto().sha256(&data).into()
+    }
+
+    /// Size of a single fee leg, or `None` if it is malformed
+    fn fee_amount(immutables: &Immutables, leg: &FeeLeg) -> Option<i128> {
+        match leg.amount {
+            FeeAmount::Fixed(amount) if amount >= 0 => Some(amount),
+            FeeAmount::Bps(bps) if (bps as i128) <= BPS_DENOMINATOR => {
+                Some(immutables.amount.checked_mul(bps as i128)? / BPS_DENOMINATOR)
+            }
+            _ => None,
+        }
+    }
+
+    /// Sum of all fee legs, or `None` if there are too many or one is malformed
+    fn total_fees(immutables: &Immutables) -> Option<i128> {
+        if immutables.fees.len() > MAX_FEE_LEGS {
+            return None;
+        }
+        let mut total: i128 = 0;
+        for leg in immutables.fees.iter() {
+            total = total.checked_add(Self::fee_amount(immutables, &leg)?)?;
+        }
+        Some(total)
+    }
+
+    /// Deploy, fund and initialize a source escrow once the maker has authorized it
+    fn create_escrow(env: &Env, src_immutables: Immutables, funding: Funding) -> Result<Address, Error> {
+        Self::validate_immutables(&src_immutables)?;
+        Self::require_chain_id(env, &src_immutables)?;
+        Self::require_timelock_preset(env, &src_immutables)?;
+        Self::require_clawback_allowed(env, &src_immutables)?;
+        Self::require_fees_within_cap(env, &src_immutables)?;
+        Self::charge_fill_fee(env, &src_immutables.taker)?;
+
+        // Create salt from immutables hash
+        let salt = Self::compute_salt(env, &src_immutables);
+
+        // Note: In Soroban, token transfers and native XLM transfers work differently than Ethereum
+        // The safety deposit (native XLM) must already have been sent to the escrow address,
+        // while the tokens are pulled from the maker here under the maker's authorization
+
+        // Deploy the escrow at the address derived from the salt
+        let escrow_address = Self::deploy_escrow(env, &salt);
+
+        // Pull the maker's tokens into the escrow, unless the order protocol already sent them
+        if funding != Funding::Prefunded {
+            Self::pull_funds(
+                env,
+                &src_immutables.token,
+                &src_immutables.maker,
+                &escrow_address,
+                src_immutables.amount,
+                funding,
+            )?;
+        }
+
+        // Initialize the escrow with the immutables
+        Self::init_escrow(env, &escrow_address, &salt, &src_immutables)?;
+
+        Self::record_escrow(env, &escrow_address, &src_immutables);
+
+        env.events().publish(
+            (symbol_short!("created"), src_immutables.hashlock),
+            (escrow_address.clone(), src_immutables.maker),
+        );
+
+        Ok(escrow_address)
+    }
+
+    /// Reject immutables the escrow would refuse to initialize with
+    fn validate_immutables(immutables: &Immutables) -> Result<(), Error> {
+        if immutables.amount <= 0 || immutables.safety_deposit < 0 {
+            return Err(Error::InvalidImmutables);
+        }
+        match Self::total_fees(immutables) {
+            Some(total) if total <= immutables.amount => {}
+            _ => return Err(Error::InvalidImmutables),
+        }
+        if immutables.src_withdrawal_start > immutables.src_public_withdrawal_start
+            || immutables.src_public_withdrawal_start > immutables.src_cancellation_start
+            || immutables.src_withdrawal_start >= immutables.src_cancellation_start
+            || immutables.src_cancellation_start > immutables.src_public_cancellation_start
+        {
+            return Err(Error::InvalidImmutables);
+        }
+        Ok(())
+    }
+
+    /// Refuse orders naming another chain than this one as their source
+    fn require_chain_id(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        if immutables.src_chain_id != Self::get_chain_id(env.clone()) {
+            return Err(Error::ChainIdMismatch);
+        }
+        Ok(())
+    }
+
+    /// Refuse timelocks outside the preset for the order's destination chain, if there is one
+    fn require_timelock_preset(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        let Some(preset) = Self::get_timelock_preset(env.clone(), immutables.dst_chain_id) else {
+            return Ok(());
+        };
+        let seconds = |offset: u32| Self::offset_seconds(immutables, offset);
+        let src_private_withdrawal = immutables.src_public_withdrawal_start.saturating_sub(immutables.src_withdrawal_start);
+        let dst_private_withdrawal = immutables.dst_public_withdrawal_start.saturating_sub(immutables.dst_withdrawal_start);
+        let src_private_cancellation =
+            immutables.src_public_cancellation_start.saturating_sub(immutables.src_cancellation_start);
+        if seconds(immutables.src_withdrawal_start) < preset.min_finality_lock as u64
+            || seconds(immutables.dst_withdrawal_start) < preset.min_finality_lock as u64
+            || seconds(src_private_withdrawal) < preset.min_private_withdrawal as u64
+            || seconds(dst_private_withdrawal) < preset.min_private_withdrawal as u64
+            || seconds(src_private_cancellation) < preset.min_private_cancellation as u64
+            || seconds(immutables.src_public_cancellation_start) > preset.max_duration as u64
+            || seconds(immutables.dst_cancellation_start) > preset.max_duration as u64
+        {
+            return Err(Error::TimelockOutOfPolicy);
+        }
+        Ok(())
+    }
+
+    /// Timelock offset in seconds, counting ledgers at the nominal close time
+    fn offset_seconds(immutables: &Immutables, offset: u32) -> u64 {
+        match immutables.time_base {
+            TimeBase::Timestamp => offset as u64,
+            TimeBase::Sequence => offset as u64 * LEDGER_SECONDS,
+        }
+    }
+
+    /// Refuse clawback-enabled tokens unless the order explicitly accepts them
+    fn require_clawback_allowed(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        if !immutables.allow_clawback && Self::is_clawback_token(env.clone(), immutables.token.clone()) {
+            return Err(Error::ClawbackNotAllowed);
+        }
+        Ok(())
+    }
+
+    /// Refuse fee legs adding up to more than the configured maximum
+    fn require_fees_within_cap(env: &Env, immutables: &Immutables) -> Result<(), Error> {
+        let total = Self::total_fees(immutables).ok_or(Error::InvalidImmutables)?;
+        let max_fee_bps = Self::get_max_fee_bps(env.clone()) as i128;
+
+        // Fees too large to weigh exceed any cap, a cap too large to weigh is no limit
+        let fees = total.checked_mul(BPS_DENOMINATOR).ok_or(Error::FeeTooHigh)?;
+        match immutables.amount.checked_mul(max_fee_bps) {
+            Some(cap) if fees > cap => Err(Error::FeeTooHigh),
+            _ => Ok(()),
+        }
+    }
+
+    /// Debit the per-fill fee from the resolver's FeeBank balance, if a fee bank is configured
+    fn charge_fill_fee(env: &Env, resolver: &Address) -> Result<(), Error> {
+        let fee_bank = match Self::get_fee_bank(env.clone()) {
+            Some(fee_bank) => fee_bank,
+            None => return Ok(()),
+        };
+        let fill_fee = Self::get_fill_fee(env.clone());
+        if fill_fee == 0 {
+            return Ok(());
+        }
+
+        // FeeBank errors share the factory's codes and are passed through as-is
+        match FeeBankClient::new(env, &fee_bank).try_charge(&env.current_contract_address(), resolver, &fill_fee) {
+            Ok(Ok(_)) => Ok(()),
+            Err(Ok(error)) => Err(error),
+            _ => Err(Error::EscrowCreationFailed),
+        }
+    }
+
+    /// Transfer tokens into the escrow, checking that exactly `amount` arrived
+    ///
+    /// Fee-on-transfer and rebasing tokens deliver a different amount and are rejected.
+    fn pull_funds(
+        env: &Env,
+        token: &Address,
+        from: &Address,
+        escrow: &Address,
+        amount: i128,
+        funding: Funding,
+    ) -> Result<(), Error> {
+        let token_client = token::Client::new(env, token);
+        let balance_before = token_client.balance(escrow);
+
+        let result = if funding == Funding::Allowance {
+            token_client.try_transfer_from(&env.current_contract_address(), from, escrow, &amount)
+        } else {
+            token_client.try_transfer(from, escrow, &amount)
+        };
+        match result {
+            Ok(Ok(())) => {}
+            _ => return Err(Error::TransferFailed),
+        }
+
+        if token_client.balance(escrow) - balance_before != amount {
+            return Err(Error::BalanceMismatch);
+        }
+        Ok(())
+    }
+
+    /// Recover the EVM address (last 20 bytes of the keccak256 of the public key) that signed a digest
+    fn recover_evm_address(env: &Env, digest: &Hash<32>, signature: &BytesN<64>, recovery_id: u32) -> BytesN<20> {
+        let public_key = env.crypto().secp256k1_recover(digest, signature, recovery_id);
+
+        // Skip the 0x04 prefix of the uncompressed key
+        let key_hash = env.crypto().keccak256(&Bytes::from(public_key).slice(1..));
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&key_hash.to_array()[12..]);
+        BytesN::from_array(env, &address)
+    }
+
+    /// Check the immutables against the terms an EVM maker signed
+    ///
+    /// The maker asset must be the escrowed token, and the hashlock, destination chain,
+    /// safety deposit and timelocks (in seconds) those of the extension the salt commits to.
+    /// Signed orders carry no fee legs and do not opt into clawback tokens.
+    fn require_signed_terms(env: &Env, immutables: &Immutables, order: &EvmOrder, extension: &Bytes) -> Result<(), Error> {
+        if order.maker_asset != extension::evm_asset(env, &immutables.token) || !extension::commits_to(env, order, extension) {
+            return Err(Error::InvalidImmutables);
+        }
+        let args = extension::escrow_args(extension).ok_or(Error::InvalidImmutables)?;
+
+        let timelocks = [
+            immutables.src_withdrawal_start,
+            immutables.src_public_withdrawal_start,
+            immutables.src_cancellation_start,
+            immutables.src_public_cancellation_start,
+            immutables.dst_withdrawal_start,
+            immutables.dst_public_withdrawal_start,
+            immutables.dst_cancellation_start,
+        ];
+        if args.hashlock_info != immutables.hashlock
+            || args.dst_chain_id != immutables.dst_chain_id
+            || i128::try_from(args.src_safety_deposit) != Ok(immutables.safety_deposit)
+            || args.timelocks != timelocks
+            || immutables.time_base != TimeBase::Timestamp
+            || immutables.allow_clawback
+            || !immutables.fees.is_empty()
+        {
+            return Err(Error::InvalidImmutables);
+        }
+        Ok(())
+    }
+
+    /// Deploy the EscrowSrc contract at the address derived from the salt
+    fn deploy_escrow(env: &Env, salt: &BytesN<32>) -> Address {
+        let wasm_hash: BytesN<32> = env.storage().instance().get(&symbol_short!("wasm")).unwrap();
+        env.deployer().with_current_contract(salt.clone()).deploy_v2(wasm_hash, ())
+    }
+
+    /// Add a newly created escrow to the order, maker and taker indices
+    fn record_escrow(env: &Env, escrow: &Address, immutables: &Immutables) {
+        let record = EscrowRecord {
+            escrow: escrow.clone(),
+            order_hash: immutables.order_hash.clone(),
+            maker: immutables.maker.clone(),
+            taker: immutables.taker.clone(),
+            created_at: env.ledger().timestamp(),
+            order_index: Self::append(env, RegistryIndex::Order(immutables.order_hash.clone()), escrow),
+            maker_index: Self::append(env, RegistryIndex::Maker(immutables.maker.clone()), escrow),
+            taker_index: Self::append(env, RegistryIndex::Taker(immutables.taker.clone()), escrow),
+        };
+        env.storage().persistent().set(&(symbol_short!("escrow"), escrow.clone()), &record);
+        Self::extend_record_ttl(env, &record);
+    }
+
+    /// Append an escrow to an index, returning its position
+    fn append(env: &Env, index: RegistryIndex, escrow: &Address) -> u32 {
+        let count_key = (symbol_short!("count"), index.clone());
+        let position: u32 = env.storage().persistent().get(&count_key).unwrap_or(0);
+        env.storage().persistent().set(&(symbol_short!("slot"), index, position), escrow);
+        env.storage().persistent().set(&count_key, &(position + 1));
+        position
+    }
+
+    /// Remove a record and its index slots; the pages skip the emptied slots
+    fn remove_record(env: &Env, record: &EscrowRecord) {
+        let storage = env.storage().persistent();
+        storage.remove(&(symbol_short!("escrow"), record.escrow.clone()));
+        storage.remove(&(symbol_short!("slot"), RegistryIndex::Order(record.order_hash.clone()), record.order_index));
+        storage.remove(&(symbol_short!("slot"), RegistryIndex::Maker(record.maker.clone()), record.maker_index));
+        storage.remove(&(symbol_short!("slot"), RegistryIndex::Taker(record.taker.clone()), record.taker_index));
+    }
+
+    /// Read the records of an index at positions `cursor` to `cursor + limit`
+    ///
+    /// Slots emptied by finalization are skipped without reading further, so a page can
+    /// hold fewer than `limit` records while a cursor is still handed back.
+    fn page(env: &Env, index: RegistryIndex, cursor: u32, limit: u32) -> EscrowPage {
+        let count: u32 = env.storage().persistent().get(&(symbol_short!("count"), index.clone())).unwrap_or(0);
+        let end = cursor.saturating_add(limit.min(MAX_PAGE_SIZE)).min(count);
+
+        let mut records = Vec::new(env);
+        for position in cursor..end {
+            let slot: Option<Address> = env.storage().persistent().get(&(symbol_short!("slot"), index.clone(), position));
+            if let Some(record) = slot.and_then(|escrow| Self::get_escrow(env.clone(), escrow)) {
+                records.push_back(record);
+            }
+        }
+
+        let next_cursor = if end < count { Some(end) } else { None };
+        EscrowPage { records, next_cursor }
+    }
+
+    /// Extend the TTL of a record, its index slots and the index counters
+    fn extend_record_ttl(env: &Env, record: &EscrowRecord) {
+        let storage = env.storage().persistent();
+        storage.extend_ttl(&(symbol_short!("escrow"), record.escrow.clone()), REGISTRY_LIFETIME_THRESHOLD, REGISTRY_BUMP_AMOUNT);
+
+        let indices = [
+            (RegistryIndex::Order(record.order_hash.clone()), record.order_index),
+            (RegistryIndex::Maker(record.maker.clone()), record.maker_index),
+            (RegistryIndex::Taker(record.taker.clone()), record.taker_index),
+        ];
+        for (index, position) in indices {
+            storage.extend_ttl(&(symbol_short!("count"), index.clone()), REGISTRY_LIFETIME_THRESHOLD, REGISTRY_BUMP_AMOUNT);
+            storage.extend_ttl(&(symbol_short!("slot"), index, position), REGISTRY_LIFETIME_THRESHOLD, REGISTRY_BUMP_AMOUNT);
+        }
     }
 
     /// Initialize the escrow contract
@@ -105,17 +821,18 @@ impl EscrowSrcFactory {
         env: &Env,
         escrow_address: &Address,
         salt: &BytesN<32>,
-        _immutables: &Immutables,
+        immutables: &Immutables,
     ) -> Result<(), Error> {
-        // In a real implementation, you would:
-        // 1. Deploy the EscrowSrc contract to the computed address
-        // 2. Call the init function on the deployed escrow contract
-        // 3. Pass the immutables and other parameters
-        // 4. Handle any errors from the initialization
-        
-        // For now, we'll simulate the initialization
-        log!(&env, "EscrowInitialized", escrow_address, salt);
-        
+        let native_token: Address = env.storage().instance().get(&symbol_short!("native")).unwrap();
+        let escrow = EscrowSrcClient::new(env, escrow_address);
+
+        // Escrow errors share the factory's codes and are passed through as-is
+        match escrow.try_init(&env.current_contract_address(), salt, immutables, &native_token) {
+            Ok(Ok(())) => {}
+            Err(Ok(error)) => return Err(error),
+            _ => return Err(Error::EscrowCreationFailed),
+        }
+
         Ok(())
     }
 }
@@ -125,59 +842,180 @@ mod test {
     extern crate std;
     
     use super::*;
+    use escrow_src::State;
     use soroban_sdk::{
-        Address, BytesN, Env, IntoVal,
-        testutils::{Address as _, Ledger as _, AuthorizedFunction, AuthorizedInvocation},
+        Address, Bytes, BytesN, Env, vec, IntoVal, InvokeError, Symbol, Val, U256,
+        testutils::{
+            Address as _, AuthorizedFunction, AuthorizedInvocation, Events as _, Ledger, MockAuth, MockAuthInvoke,
+            storage::Persistent,
+        },
+        token::{StellarAssetClient, TokenClient},
     };
+    use k256::ecdsa::SigningKey;
 
-    #[test]
-    fn test_create_src_escrow() {
+    /// EscrowSrc wasm the factory deploys (build it with `make build` first)
+    mod escrow_wasm {
+        soroban_sdk::contractimport!(file = "../../target/wasm32v1-none/release/escrow_src.wasm");
+    }
+
+    /// EscrowDst wasm, whose `init` the factory cannot call
+    mod wrong_escrow_wasm {
+        soroban_sdk::contractimport!(file = "../../target/wasm32v1-none/release/escrow_dst.wasm");
+    }
+
+    /// Token whose transfers deliver one unit less than requested
+    #[contract]
+    struct LossyToken;
+
+    #[contractimpl]
+    impl LossyToken {
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let balance = Self::balance(env.clone(), to.clone());
+            env.storage().persistent().set(&to, &(balance + amount));
+        }
+
+        pub fn balance(env: Env, id: Address) -> i128 {
+            env.storage().persistent().get(&id).unwrap_or(0)
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            let from_balance = Self::balance(env.clone(), from.clone());
+            env.storage().persistent().set(&from, &(from_balance - amount));
+            Self::mint(env, to, amount - 1);
+        }
+    }
+
+    struct Setup<'a> {
+        env: Env,
+        contract_id: Address,
+        client: EscrowSrcFactoryClient<'a>,
+        native_token: Address,
+    }
+
+    fn setup<'a>() -> Setup<'a> {
+        setup_with_wasm(escrow_wasm::WASM)
+    }
+
+    /// Topics and data of the events the factory published in the last invocation
+    fn factory_events(s: &Setup) -> Vec<(Vec<Val>, Val)> {
+        let mut events = Vec::new(&s.env);
+        for (contract, topics, data) in s.env.events().all().iter() {
+            if contract == s.client.address {
+                events.push_back((topics, data));
+            }
+        }
+        events
+    }
+
+    fn setup_with_wasm<'a>(escrow_wasm: &[u8]) -> Setup<'a> {
         let env = Env::default();
-        
+
+        let admin = Address::generate(&env);
+        let wasm_hash = env.deployer().upload_contract_wasm(escrow_wasm);
+        let native_token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+
         // Register the factory contract
-        let contract_id = env.register(EscrowSrcFactory, ());
+        let contract_id = env.register(EscrowSrcFactory, (admin, wasm_hash, native_token.clone(), 1000u64));
         let client = EscrowSrcFactoryClient::new(&env, &contract_id);
-        
-        // Create test immutables
-        let immutables = Immutables {
-            order_hash: BytesN::from_array(&env, &[1u8; 32]),
-            hashlock: BytesN::from_array(&env, &[2u8; 32]),
-            maker: Address::generate(&env),
-            taker: Address::generate(&env),
-            token: Address::generate(&env),
+
+        Setup { env, contract_id, client, native_token }
+    }
+
+    fn create_immutables(env: &Env, token: &Address) -> Immutables {
+        Immutables {
+            order_hash: BytesN::from_array(env, &[1u8; 32]),
+            hashlock: BytesN::from_array(env, &[2u8; 32]),
+            maker: Address::generate(env),
+            taker: Address::generate(env),
+            token: token.clone(),
             amount: 1000,
             safety_deposit: 100,
+            src_chain_id: 1000,
+            dst_chain_id: 1,
+            time_base: TimeBase::Timestamp,
             deployed_at: env.ledger().timestamp(),
             src_withdrawal_start: 3600,      // 1 hour
             src_public_withdrawal_start: 7200, // 2 hours
             src_cancellation_start: 10800,     // 3 hours
             src_public_cancellation_start: 14400, // 4 hours
-        };
+            dst_withdrawal_start: 3600,
+            dst_public_withdrawal_start: 7200,
+            dst_cancellation_start: 10800,
+            allow_clawback: false,
+            fees: Vec::new(env),
+        }
+    }
+
+    /// Send the safety deposit to where the factory will deploy the escrow
+    fn prepare_escrow(s: &Setup, immutables: &Immutables) -> Address {
+        let escrow_address = s.client.compute_escrow_address(immutables);
+        StellarAssetClient::new(&s.env, &s.native_token).mint(&escrow_address, &immutables.safety_deposit);
+        escrow_address
+    }
 
-        // Test that we can compute the escrow address
-        let escrow_address = client.compute_escrow_address(&immutables);
-        // Just verify the function doesn't panic and returns an address
-        assert!(escrow_address.to_string().len() > 0);
+    /// Immutables for a Stellar asset the maker holds exactly `amount` of
+    fn funded_immutables(s: &Setup) -> Immutables {
+        let token = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+        let immutables = create_immutables(&s.env, &token);
+        StellarAssetClient::new(&s.env, &token).mint(&immutables.maker, &immutables.amount);
+        immutables
+    }
+
+    #[test]
+    fn test_create_src_escrow() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let immutables = funded_immutables(&s);
+        let escrow_address = prepare_escrow(&s, &immutables);
+
+        assert_eq!(s.client.createsrc(&immutables), escrow_address);
+
+        // The maker's tokens moved into an initialized escrow
+        let token = TokenClient::new(&s.env, &immutables.token);
+        assert_eq!(token.balance(&escrow_address), 1000);
+        assert_eq!(token.balance(&immutables.maker), 0);
+
+        let escrow = escrow_src::EscrowSrcClient::new(&s.env, &escrow_address);
+        assert_eq!(escrow.get_state(), State::Active);
+        assert_eq!(escrow.get_immutables().maker, immutables.maker);
+    }
+
+    #[test]
+    fn test_post_interaction() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let immutables = funded_immutables(&s);
+        let escrow_address = prepare_escrow(&s, &immutables);
+
+        // No order protocol configured yet
+        assert_eq!(s.client.try_post_interaction(&immutables), Err(Ok(Error::NotInitialized)));
+        let protocol = Address::generate(&s.env);
+        s.client.set_order_protocol(&protocol);
+        assert_eq!(s.client.get_order_protocol(), Some(protocol));
+
+        // The protocol has to send the maker's tokens first
+        assert_eq!(s.client.try_post_interaction(&immutables), Err(Ok(Error::InsufficientBalance)));
+
+        let token = TokenClient::new(&s.env, &immutables.token);
+        token.transfer(&immutables.maker, &escrow_address, &immutables.amount);
+        assert_eq!(s.client.post_interaction(&immutables), escrow_address);
+        assert_eq!(token.balance(&escrow_address), 1000);
+        assert_eq!(escrow_src::EscrowSrcClient::new(&s.env, &escrow_address).get_state(), State::Active);
+
+        // Only the protocol can skip the maker's authorization
+        let other = funded_immutables(&s);
+        prepare_escrow(&s, &other);
+        s.env.set_auths(&[]);
+        assert!(s.client.try_post_interaction(&other).is_err());
     }
 
     #[test]
     fn test_compute_salt() {
         let env = Env::default();
         
-        let immutables = Immutables {
-            order_hash: BytesN::from_array(&env, &[1u8; 32]),
-            hashlock: BytesN::from_array(&env, &[2u8; 32]),
-            maker: Address::generate(&env),
-            taker: Address::generate(&env),
-            token: Address::generate(&env),
-            amount: 1000,
-            safety_deposit: 100,
-            deployed_at: env.ledger().timestamp(),
-            src_withdrawal_start: 3600,
-            src_public_withdrawal_start: 7200,
-            src_cancellation_start: 10800,
-            src_public_cancellation_start: 14400,
-        };
+        let immutables = create_immutables(&env, &Address::generate(&env));
 
         let salt = EscrowSrcFactory::compute_salt(&env, &immutables);
         assert!(salt != BytesN::from_array(&env, &[0u8; 32]));
@@ -185,48 +1023,739 @@ mod test {
         // Test that same immutables produce same salt
         let salt2 = EscrowSrcFactory::compute_salt(&env, &immutables);
         assert_eq!(salt, salt2);
+
+        // Every term of the order but the deployment time is part of the salt
+        let mut deployed_later = immutables.clone();
+        deployed_later.deployed_at += 60;
+        assert_eq!(EscrowSrcFactory::compute_salt(&env, &deployed_later), salt);
+        let mut other_amount = immutables.clone();
+        other_amount.amount += 1;
+        assert!(EscrowSrcFactory::compute_salt(&env, &other_amount) != salt);
+
+        // The same order on another network has another salt
+        env.ledger().set_network_id([7u8; 32]);
+        assert!(EscrowSrcFactory::compute_salt(&env, &immutables) != salt);
+    }
+
+    #[test]
+    fn test_chain_ids_change_address() {
+        let s = setup();
+        let immutables = create_immutables(&s.env, &Address::generate(&s.env));
+        let address = s.client.compute_escrow_address(&immutables);
+
+        let mut other_src = immutables.clone();
+        other_src.src_chain_id = 1001;
+        assert!(s.client.compute_escrow_address(&other_src) != address);
+
+        let mut other_dst = immutables.clone();
+        other_dst.dst_chain_id = 56;
+        assert!(s.client.compute_escrow_address(&other_dst) != address);
+    }
+
+    #[test]
+    fn test_chain_id_mismatch() {
+        let s = setup();
+        s.env.mock_all_auths();
+        assert_eq!(s.client.get_chain_id(), 1000);
+
+        // Orders must name this chain as their source
+        let mut immutables = funded_immutables(&s);
+        immutables.src_chain_id = 1;
+        prepare_escrow(&s, &immutables);
+        assert_eq!(s.client.try_createsrc(&immutables), Err(Ok(Error::ChainIdMismatch)));
+
+        // The chain ID is part of the salt, so the escrow lands at another address
+        immutables.src_chain_id = 1000;
+        prepare_escrow(&s, &immutables);
+        s.client.createsrc(&immutables);
+    }
+
+    #[test]
+    fn test_timelock_preset() {
+        let s = setup();
+        s.env.mock_all_auths();
+        let preset = TimelockPreset {
+            min_finality_lock: 3600,
+            min_private_withdrawal: 3600,
+            min_private_cancellation: 3600,
+            max_duration: 14400,
+        };
+
+        // The minimum windows have to fit within the maximum duration
+        let too_short = TimelockPreset { max_duration: 10799, ..preset.clone() };
+        assert_eq!(s.client.try_set_timelock_preset(&1, &too_short), Err(Ok(Error::InvalidTimelockPreset)));
+
+        s.client.set_timelock_preset(&1, &preset);
+        assert_eq!(s.client.get_timelock_preset(&1), Some(preset.clone()));
+        assert_eq!(s.client.get_timelock_preset(&2), None);
+
+        let immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
+
+        // Each bound of the preset is enforced against the destination chain's entry
+        let violations = [
+            TimelockPreset { min_finality_lock: 3601, ..preset.clone() },
+            TimelockPreset { min_private_withdrawal: 3601, ..preset.clone() },
+            TimelockPreset { min_private_cancellation: 3601, ..preset.clone() },
+            TimelockPreset { max_duration: 14399, ..preset.clone() },
+        ];
+        for violation in violations {
+            s.client.set_timelock_preset(&1, &violation);
+            assert_eq!(s.client.try_createsrc(&immutables), Err(Ok(Error::TimelockOutOfPolicy)));
+        }
+
+        // Ledger counts are held to the preset at the nominal close time
+        s.client.set_timelock_preset(&1, &preset);
+        let mut in_ledgers = immutables.clone();
+        in_ledgers.time_base = TimeBase::Sequence;
+        assert_eq!(s.client.try_createsrc(&in_ledgers), Err(Ok(Error::TimelockOutOfPolicy)));
+
+        // Orders to chains without a preset are not checked
+        s.client.remove_timelock_preset(&1);
+        assert_eq!(s.client.get_timelock_preset(&1), None);
+        s.client.set_timelock_preset(&2, &TimelockPreset { min_finality_lock: 7200, ..preset.clone() });
+        s.client.set_timelock_preset(&1000, &TimelockPreset { min_finality_lock: 7200, ..preset.clone() });
+        s.client.createsrc(&immutables);
     }
 
     #[test]
     fn test_create_src_escrow_with_auth() {
-        let env = Env::default();
-        
-        // Register the factory contract
-        let contract_id = env.register(EscrowSrcFactory, ());
-        let client = EscrowSrcFactoryClient::new(&env, &contract_id);
-        
-        let immutables = Immutables {
-            order_hash: BytesN::from_array(&env, &[1u8; 32]),
-            hashlock: BytesN::from_array(&env, &[2u8; 32]),
-            maker: Address::generate(&env),
-            taker: Address::generate(&env),
-            token: Address::generate(&env),
-            amount: 1000,
-            safety_deposit: 100,
-            deployed_at: env.ledger().timestamp(),
-            src_withdrawal_start: 3600,
-            src_public_withdrawal_start: 7200,
-            src_cancellation_start: 10800,
-            src_public_cancellation_start: 14400,
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let immutables = funded_immutables(&s);
+        let escrow_address = prepare_escrow(&s, &immutables);
+
+        // Without the maker's authorization the call is rejected by the host
+        s.env.set_auths(&[]);
+        assert!(s.client.try_createsrc(&immutables).is_err());
+
+        s.env.mock_all_auths();
+        s.client.createsrc(&immutables);
+
+        // The maker authorized the creation and the token transfer into the escrow,
+        // the taker the creation it pays the fill fee for
+        assert_eq!(
+            s.env.auths(),
+            std::vec![(
+                immutables.maker.clone(),
+                AuthorizedInvocation {
+                    function: AuthorizedFunction::Contract((
+                        s.contract_id.clone(),
+                        Symbol::new(&s.env, "createsrc"),
+                        (immutables.clone(),).into_val(&s.env),
+                    )),
+                    sub_invocations: std::vec![AuthorizedInvocation {
+                        function: AuthorizedFunction::Contract((
+                            immutables.token.clone(),
+                            Symbol::new(&s.env, "transfer"),
+                            (immutables.maker.clone(), escrow_address, immutables.amount).into_val(&s.env),
+                        )),
+                        sub_invocations: std::vec![],
+                    }],
+                }
+            ), (
+                immutables.taker.clone(),
+                AuthorizedInvocation {
+                    function: AuthorizedFunction::Contract((
+                        s.contract_id.clone(),
+                        Symbol::new(&s.env, "createsrc"),
+                        (immutables.clone(),).into_val(&s.env),
+                    )),
+                    sub_invocations: std::vec![],
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_create_src_escrow_invalid_immutables() {
+        let s = setup();
+        s.env.mock_all_auths();
+        let token = Address::generate(&s.env);
+
+        let mut zero_amount = create_immutables(&s.env, &token);
+        zero_amount.amount = 0;
+        assert_eq!(s.client.try_createsrc(&zero_amount), Err(Ok(Error::InvalidImmutables)));
+
+        let mut negative_deposit = create_immutables(&s.env, &token);
+        negative_deposit.safety_deposit = -1;
+        assert_eq!(s.client.try_createsrc(&negative_deposit), Err(Ok(Error::InvalidImmutables)));
+
+        let mut unordered = create_immutables(&s.env, &token);
+        unordered.src_cancellation_start = unordered.src_withdrawal_start;
+        assert_eq!(s.client.try_createsrc(&unordered), Err(Ok(Error::InvalidImmutables)));
+    }
+
+    #[test]
+    fn test_fee_on_transfer_token_rejected() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let token = s.env.register(LossyToken, ());
+        let immutables = create_immutables(&s.env, &token);
+        LossyTokenClient::new(&s.env, &token).mint(&immutables.maker, &immutables.amount);
+        let escrow_address = prepare_escrow(&s, &immutables);
+
+        assert_eq!(s.client.try_createsrc(&immutables), Err(Ok(Error::BalanceMismatch)));
+        assert_eq!(LossyTokenClient::new(&s.env, &token).balance(&escrow_address), 0);
+    }
+
+    #[test]
+    fn test_transfer_failed() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        // The maker holds none of the tokens
+        let token = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+        let immutables = create_immutables(&s.env, &token);
+        prepare_escrow(&s, &immutables);
+
+        assert_eq!(s.client.try_createsrc(&immutables), Err(Ok(Error::TransferFailed)));
+    }
+
+    #[test]
+    fn test_missing_safety_deposit() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let immutables = funded_immutables(&s);
+
+        // The escrow refuses to initialize without the safety deposit
+        assert_eq!(s.client.try_createsrc(&immutables), Err(Ok(Error::InsufficientBalance)));
+    }
+
+    #[test]
+    fn test_escrow_creation_failed() {
+        let s = setup_with_wasm(wrong_escrow_wasm::WASM);
+        s.env.mock_all_auths();
+
+        // The deployed contract rejects the initialization call
+        let immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
+        assert_eq!(s.client.try_createsrc(&immutables), Err(Ok(Error::EscrowCreationFailed)));
+    }
+
+    #[test]
+    fn test_clawback_token() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let mut immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
+
+        assert!(!s.client.is_clawback_token(&immutables.token));
+        s.client.set_clawback_token(&immutables.token, &true);
+        assert!(s.client.is_clawback_token(&immutables.token));
+
+        assert_eq!(s.client.try_createsrc(&immutables), Err(Ok(Error::ClawbackNotAllowed)));
+
+        // The order can explicitly accept the clawback risk
+        immutables.allow_clawback = true;
+        let escrow_address = prepare_escrow(&s, &immutables);
+        assert_eq!(s.client.createsrc(&immutables), escrow_address);
+
+        s.client.set_clawback_token(&immutables.token, &false);
+        assert!(!s.client.is_clawback_token(&immutables.token));
+    }
+
+    #[test]
+    fn test_set_clawback_token_requires_admin() {
+        let s = setup();
+        let token = Address::generate(&s.env);
+
+        assert!(s.client.try_set_clawback_token(&token, &true).is_err());
+        assert!(!s.client.is_clawback_token(&token));
+    }
+
+    #[test]
+    fn test_fee_cap() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let mut immutables = funded_immutables(&s);
+        let integrator = Address::generate(&s.env);
+        immutables.fees = vec![&s.env, FeeLeg { recipient: integrator, amount: FeeAmount::Bps(100) }];
+
+        // No fees are allowed until a maximum is configured
+        assert_eq!(s.client.get_max_fee_bps(), 0);
+        assert_eq!(s.client.try_createsrc(&immutables), Err(Ok(Error::FeeTooHigh)));
+
+        s.client.set_max_fee_bps(&100);
+        assert_eq!(s.client.get_max_fee_bps(), 100);
+
+        let mut over_cap = immutables.clone();
+        over_cap.fees.push_back(FeeLeg { recipient: Address::generate(&s.env), amount: FeeAmount::Fixed(1) });
+        let result = s.client.try_createsrc(&over_cap);
+        assert_eq!(result, Err(Ok(Error::FeeTooHigh)));
+
+        // Fees too large to weigh against the cap are rejected rather than trapping
+        let mut oversized = immutables.clone();
+        oversized.amount = i128::MAX / 2;
+        oversized.fees = vec![&s.env, FeeLeg { recipient: Address::generate(&s.env), amount: FeeAmount::Fixed(i128::MAX / 4) }];
+        assert_eq!(s.client.try_createsrc(&oversized), Err(Ok(Error::FeeTooHigh)));
+
+        let escrow_address = prepare_escrow(&s, &immutables);
+        assert_eq!(s.client.createsrc(&immutables), escrow_address);
+    }
+
+    #[test]
+    fn test_fill_fee() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let admin = Address::generate(&s.env);
+        let fee_bank_id = s.env.register(fee_bank::FeeBank, (admin, s.native_token.clone()));
+        let fee_bank = fee_bank::FeeBankClient::new(&s.env, &fee_bank_id);
+        fee_bank.set_charger(&s.contract_id, &true);
+
+        s.client.set_fee_bank(&fee_bank_id, &10);
+        assert_eq!(s.client.get_fee_bank(), Some(fee_bank_id.clone()));
+        assert_eq!(s.client.get_fill_fee(), 10);
+        assert_eq!(s.client.try_set_fee_bank(&fee_bank_id, &-1), Err(Ok(Error::InvalidAmount)));
+
+        let immutables = funded_immutables(&s);
+        let escrow_address = prepare_escrow(&s, &immutables);
+
+        // The resolver has not pre-paid any fees yet
+        assert_eq!(s.client.try_createsrc(&immutables), Err(Ok(Error::InsufficientFeeBalance)));
+
+        StellarAssetClient::new(&s.env, &s.native_token).mint(&immutables.taker, &15);
+        fee_bank.deposit(&immutables.taker, &15);
+
+        assert_eq!(s.client.createsrc(&immutables), escrow_address);
+        assert_eq!(fee_bank.balance(&immutables.taker), 5);
+        assert_eq!(fee_bank.collected(), 10);
+    }
+
+    #[test]
+    fn test_fill_fee_requires_taker_auth() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let admin = Address::generate(&s.env);
+        let fee_bank_id = s.env.register(fee_bank::FeeBank, (admin, s.native_token.clone()));
+        let fee_bank = fee_bank::FeeBankClient::new(&s.env, &fee_bank_id);
+        fee_bank.set_charger(&s.contract_id, &true);
+        s.client.set_fee_bank(&fee_bank_id, &10);
+
+        let immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
+        StellarAssetClient::new(&s.env, &s.native_token).mint(&immutables.taker, &15);
+        fee_bank.deposit(&immutables.taker, &15);
+
+        // The maker names a taker that never agreed to the fill
+        s.env.mock_auths(&[MockAuth {
+            address: &immutables.maker,
+            invoke: &MockAuthInvoke {
+                contract: &s.contract_id,
+                fn_name: "createsrc",
+                args: (immutables.clone(),).into_val(&s.env),
+                sub_invokes: &[],
+            },
+        }]);
+        assert_eq!(s.client.try_createsrc(&immutables), Err(Err(InvokeError::Abort)));
+
+        // The taker's fee deposit is untouched
+        assert_eq!(fee_bank.balance(&immutables.taker), 15);
+        assert_eq!(fee_bank.collected(), 0);
+    }
+
+    #[test]
+    fn test_create_src_escrows() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        // Two orders from the same maker, filled by the same taker
+        let first = funded_immutables(&s);
+        let mut second = funded_immutables(&s);
+        second.maker = first.maker.clone();
+        second.taker = first.taker.clone();
+        second.order_hash = BytesN::from_array(&s.env, &[9u8; 32]);
+        StellarAssetClient::new(&s.env, &second.token).mint(&second.maker, &second.amount);
+        let first_address = prepare_escrow(&s, &first);
+        let second_address = prepare_escrow(&s, &second);
+
+        let batch = vec![&s.env, first.clone(), second.clone()];
+        let escrows = s.client.createsrcs(&batch);
+        assert_eq!(escrows, vec![&s.env, first_address.clone(), second_address.clone()]);
+
+        // Each escrow of the batch gets its own creation event
+        let created = |immutables: &Immutables, escrow: &Address| (
+            (symbol_short!("created"), immutables.hashlock.clone()).into_val(&s.env),
+            (escrow.clone(), immutables.maker.clone()).into_val(&s.env),
+        );
+        let events = vec![&s.env, created(&first, &first_address), created(&second, &second_address)];
+        assert_eq!(factory_events(&s), events);
+
+        // The maker and the taker each sign a single authorization covering the whole batch
+        let auths = s.env.auths();
+        assert_eq!(auths.len(), 2);
+        assert_eq!(auths[0].0, first.maker);
+        assert_eq!(auths[0].1.sub_invocations.len(), 2);
+        assert_eq!(auths[1].0, first.taker);
+
+        assert_eq!(TokenClient::new(&s.env, &first.token).balance(&first_address), 1000);
+        assert_eq!(TokenClient::new(&s.env, &second.token).balance(&second_address), 1000);
+    }
+
+    #[test]
+    fn test_create_src_escrows_atomic() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let first = funded_immutables(&s);
+        let first_address = prepare_escrow(&s, &first);
+        let mut second = funded_immutables(&s);
+        second.order_hash = BytesN::from_array(&s.env, &[9u8; 32]);
+        second.amount = 0;
+
+        let result = s.client.try_createsrcs(&vec![&s.env, first.clone(), second]);
+        assert_eq!(result, Err(Ok(Error::InvalidImmutables)));
+
+        // The first escrow was rolled back with the rest of the batch, deployment included
+        assert_eq!(TokenClient::new(&s.env, &first.token).balance(&first.maker), 1000);
+        let escrow = escrow_src::EscrowSrcClient::new(&s.env, &first_address);
+        assert_eq!(escrow.try_get_state(), Err(Err(InvokeError::Abort)));
+    }
+
+    #[test]
+    fn test_registry() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        // Two orders from one maker, the first and third filled by the same taker
+        let first = funded_immutables(&s);
+        let mut second = funded_immutables(&s);
+        second.maker = first.maker.clone();
+        second.order_hash = BytesN::from_array(&s.env, &[9u8; 32]);
+        StellarAssetClient::new(&s.env, &second.token).mint(&second.maker, &second.amount);
+        let mut third = funded_immutables(&s);
+        third.hashlock = BytesN::from_array(&s.env, &[8u8; 32]);
+        third.taker = first.taker.clone();
+
+        let mut escrows = std::vec::Vec::new();
+        for immutables in [&first, &second, &third] {
+            prepare_escrow(&s, immutables);
+            escrows.push(s.client.createsrc(immutables));
+        }
+
+        let record = s.client.get_escrow(&escrows[0]).unwrap();
+        assert_eq!(record.maker, first.maker);
+        assert_eq!(record.created_at, s.env.ledger().timestamp());
+        assert_eq!(s.client.get_escrow(&Address::generate(&s.env)), None);
+
+        // Pages follow creation order and hand back a cursor while records are left
+        let page = s.client.escrows_by_maker(&first.maker, &0, &1);
+        assert_eq!(page.records.get(0).unwrap().escrow, escrows[0]);
+        assert_eq!(page.next_cursor, Some(1));
+        let page = s.client.escrows_by_maker(&first.maker, &1, &10);
+        assert_eq!(page.records.len(), 1);
+        assert_eq!(page.records.get(0).unwrap().escrow, escrows[1]);
+        assert_eq!(page.next_cursor, None);
+
+        let page = s.client.escrows_by_order(&first.order_hash, &0, &10);
+        assert_eq!(page.records.len(), 2);
+        assert_eq!(page.records.get(1).unwrap().escrow, escrows[2]);
+
+        let page = s.client.escrows_by_taker(&first.taker, &0, &10);
+        assert_eq!(page.records.len(), 2);
+        assert_eq!(page.records.get(0).unwrap().escrow, escrows[0]);
+        assert_eq!(page.records.get(1).unwrap().escrow, escrows[2]);
+
+        assert_eq!(s.client.escrows_by_taker(&second.maker, &5, &10).records.len(), 0);
+    }
+
+    #[test]
+    fn test_registry_ttl() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let immutables = funded_immutables(&s);
+        prepare_escrow(&s, &immutables);
+        let escrow = s.client.createsrc(&immutables);
+
+        let record_ttl = || {
+            s.env.as_contract(&s.contract_id, || {
+                s.env.storage().persistent().get_ttl(&(symbol_short!("escrow"), escrow.clone()))
+            })
         };
+        assert_eq!(record_ttl(), REGISTRY_BUMP_AMOUNT);
 
-        // Test with proper maker authorization
-        env.auths().push((
-            immutables.maker.clone(),
-            AuthorizedInvocation {
-                function: AuthorizedFunction::Contract((
-                    contract_id.clone(),
-                    symbol_short!("createsrc"),
-                    (immutables.clone(),).into_val(&env),
-                )),
-                sub_invocations: std::vec![],
-            }
-        ));
+        // Keep the factory itself live while time passes
+        s.env.as_contract(&s.contract_id, || {
+            s.env.storage().instance().extend_ttl(REGISTRY_BUMP_AMOUNT, REGISTRY_BUMP_AMOUNT);
+        });
+        s.env.ledger().with_mut(|li| {
+            li.sequence_number += 2 * DAY_IN_LEDGERS;
+        });
+        assert_eq!(record_ttl(), REGISTRY_BUMP_AMOUNT - 2 * DAY_IN_LEDGERS);
+
+        s.client.extend_escrow_ttl(&escrow);
+        assert_eq!(record_ttl(), REGISTRY_BUMP_AMOUNT);
+
+        assert_eq!(s.client.try_extend_escrow_ttl(&Address::generate(&s.env)), Err(Ok(Error::InvalidAddress)));
+    }
+
+    #[test]
+    fn test_finalize_escrow() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let secret = BytesN::from_array(&s.env, &[7u8; 32]);
+        let mut immutables = funded_immutables(&s);
+        immutables.hashlock = s.env.crypto().sha256(&Bytes::from_slice(&s.env, &secret.to_array())).into();
+        prepare_escrow(&s, &immutables);
+        let escrow_address = s.client.createsrc(&immutables);
+        let escrow = escrow_src::EscrowSrcClient::new(&s.env, &escrow_address);
+
+        // Escrow errors are passed through while it is still active
+        assert_eq!(s.client.try_finalize_escrow(&escrow_address), Err(Ok(Error::EscrowActive)));
+
+        s.env.ledger().with_mut(|li| {
+            li.timestamp = immutables.deployed_at + 3600;
+        });
+        escrow.withdraw(&secret);
+        s.client.finalize_escrow(&escrow_address);
+
+        // The escrow is a tombstone and no longer listed
+        assert_eq!(escrow.get_state(), State::Withdrawn);
+        assert_eq!(s.client.get_escrow(&escrow_address), None);
+        let page = s.client.escrows_by_maker(&immutables.maker, &0, &10);
+        assert_eq!(page.records.len(), 0);
+        assert_eq!(page.next_cursor, None);
+
+        assert_eq!(s.client.try_finalize_escrow(&escrow_address), Err(Ok(Error::InvalidAddress)));
+    }
+
+    #[test]
+    fn test_page_over_finalized_escrows() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        // Five orders from one maker, all but the last settled and finalized
+        let secret = BytesN::from_array(&s.env, &[7u8; 32]);
+        let hashlock: BytesN<32> = s.env.crypto().sha256(&Bytes::from_slice(&s.env, &secret.to_array())).into();
+        let maker = Address::generate(&s.env);
+        let mut escrows = std::vec::Vec::new();
+        for n in 0..5u8 {
+            let mut immutables = funded_immutables(&s);
+            immutables.maker = maker.clone();
+            immutables.hashlock = hashlock.clone();
+            immutables.order_hash = BytesN::from_array(&s.env, &[n; 32]);
+            StellarAssetClient::new(&s.env, &immutables.token).mint(&maker, &immutables.amount);
+            prepare_escrow(&s, &immutables);
+            escrows.push(s.client.createsrc(&immutables));
+        }
+        s.env.ledger().with_mut(|li| {
+            li.timestamp += 3600;
+        });
+        for escrow in &escrows[..4] {
+            escrow_src::EscrowSrcClient::new(&s.env, escrow).withdraw(&secret);
+            s.client.finalize_escrow(escrow);
+        }
+
+        // Each page reads at most `limit` slots, handing back a cursor past the empty ones
+        let page = s.client.escrows_by_maker(&maker, &0, &2);
+        assert_eq!(page.records.len(), 0);
+        assert_eq!(page.next_cursor, Some(2));
+        let page = s.client.escrows_by_maker(&maker, &2, &2);
+        assert_eq!(page.records.len(), 0);
+        assert_eq!(page.next_cursor, Some(4));
+        let page = s.client.escrows_by_maker(&maker, &4, &2);
+        assert_eq!(page.records.len(), 1);
+        assert_eq!(page.records.get(0).unwrap().escrow, escrows[4]);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    /// EVM key signing orders, with its address
+    fn evm_signer(env: &Env, seed: u8) -> (SigningKey, BytesN<20>) {
+        let signing_key = SigningKey::from_bytes(&[seed; 32].into()).unwrap();
+        let public_key = signing_key.verifying_key().to_encoded_point(false);
+        let key_hash = env.crypto().keccak256(&Bytes::from_slice(env, &public_key.as_bytes()[1..]));
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&key_hash.to_array()[12..]);
+        (signing_key, BytesN::from_array(env, &address))
+    }
+
+    /// Limit Order Protocol v4 on Ethereum
+    fn set_domain(s: &Setup) -> BytesN<32> {
+        let lop = BytesN::from_array(&s.env, &[0x11; 20]);
+        s.client.set_eip712_domain(&1, &lop);
+        eip712::domain_separator(&s.env, 1, &lop)
+    }
+
+    /// Extension whose post-interaction carries the escrow arguments of `immutables`
+    fn extension_for(env: &Env, immutables: &Immutables) -> Bytes {
+        let mut args = [0u8; 160];
+        args[..32].copy_from_slice(&immutables.hashlock.to_array());
+        args[56..64].copy_from_slice(&immutables.dst_chain_id.to_be_bytes());
+        args[76..96].fill(0xdd);
+        args[96..112].copy_from_slice(&(immutables.safety_deposit as u128).to_be_bytes());
+        args[112..128].copy_from_slice(&(immutables.safety_deposit as u128).to_be_bytes());
+        let timelocks = [
+            immutables.src_withdrawal_start,
+            immutables.src_public_withdrawal_start,
+            immutables.src_cancellation_start,
+            immutables.src_public_cancellation_start,
+            immutables.dst_withdrawal_start,
+            immutables.dst_public_withdrawal_start,
+            immutables.dst_cancellation_start,
+        ];
+        for (stage, timelock) in timelocks.iter().enumerate() {
+            let at = 156 - 4 * stage;
+            args[at..at + 4].copy_from_slice(&timelock.to_be_bytes());
+        }
 
-        // Use the try_ prefixed method to get the Result
-        let result = client.try_createsrc(&immutables);
-        // This will fail due to escrow deployment issues in test environment
-        // In a real scenario, the contract would deploy the escrow
-        assert!(result.is_err()); // Expected to fail due to deployment issues
+        // Only the post-interaction field is set: the EVM escrow factory, then the arguments
+        let mut offsets = [0u8; 32];
+        offsets[..4].copy_from_slice(&180u32.to_be_bytes());
+        let mut extension = Bytes::from_array(env, &offsets);
+        extension.extend_from_array(&[0x33; 20]);
+        extension.extend_from_array(&args);
+        extension
     }
-} 
\ No newline at end of file
+
+    /// Order selling the escrowed amount, signed by `maker`, with its immutables and extension
+    fn signed_order(s: &Setup, domain_separator: &BytesN<32>, maker: &BytesN<20>) -> (Immutables, EvmOrder, Bytes) {
+        let mut immutables = funded_immutables(s);
+        let extension = extension_for(&s.env, &immutables);
+
+        // The low 160 bits of the salt commit to the extension
+        let mut salt = s.env.crypto().keccak256(&extension).to_array();
+        salt[..12].copy_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xf4]);
+        let order = EvmOrder {
+            salt: U256::from_be_bytes(&s.env, &Bytes::from_array(&s.env, &salt)),
+            maker: maker.clone(),
+            receiver: BytesN::from_array(&s.env, &[0; 20]),
+            maker_asset: extension::evm_asset(&s.env, &immutables.token),
+            taker_asset: BytesN::from_array(&s.env, &[0xda; 20]),
+            making_amount: U256::from_u128(&s.env, immutables.amount as u128),
+            taking_amount: U256::from_u32(&s.env, 990),
+            // Has extension
+            maker_traits: U256::from_u32(&s.env, 1).shl(249),
+        };
+        immutables.order_hash = eip712::order_hash(&s.env, domain_separator, &order).to_bytes();
+        (immutables, order, extension)
+    }
+
+    fn sign(env: &Env, signing_key: &SigningKey, digest: &BytesN<32>) -> (BytesN<64>, u32) {
+        let (signature, recovery_id) = signing_key.sign_prehash_recoverable(&digest.to_array()).unwrap();
+        (BytesN::from_array(env, &signature.to_bytes().into()), recovery_id.to_byte() as u32)
+    }
+
+    #[test]
+    fn test_create_src_escrow_signed() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let domain_separator = set_domain(&s);
+        assert_eq!(s.client.get_eip712_domain(), Some(domain_separator.clone()));
+
+        let (signing_key, evm_address) = evm_signer(&s.env, 1);
+        let (immutables, order, extension) = signed_order(&s, &domain_separator, &evm_address);
+        let escrow_address = prepare_escrow(&s, &immutables);
+        let (signature, recovery_id) = sign(&s.env, &signing_key, &immutables.order_hash);
+
+        // The signer has to be bound to the maker first
+        let result = s.client.try_createsrc_signed(&immutables, &order, &extension, &signature, &recovery_id);
+        assert_eq!(result, Err(Ok(Error::InvalidSignature)));
+        s.client.bind_evm_address(&immutables.maker, &evm_address);
+        assert_eq!(s.client.get_evm_binding(&evm_address), Some(immutables.maker.clone()));
+
+        // ...and the maker has to let the factory spend its tokens
+        let result = s.client.try_createsrc_signed(&immutables, &order, &extension, &signature, &recovery_id);
+        assert_eq!(result, Err(Ok(Error::TransferFailed)));
+        let token = TokenClient::new(&s.env, &immutables.token);
+        token.approve(&immutables.maker, &s.contract_id, &immutables.amount, &1000);
+
+        // Only the taker authorizes the call, the maker's signature and allowance cover the rest
+        s.env.mock_auths(&[MockAuth {
+            address: &immutables.taker,
+            invoke: &MockAuthInvoke {
+                contract: &s.contract_id,
+                fn_name: "createsrc_signed",
+                args: (immutables.clone(), order.clone(), extension.clone(), signature.clone(), recovery_id).into_val(&s.env),
+                sub_invokes: &[],
+            },
+        }]);
+        assert_eq!(s.client.createsrc_signed(&immutables, &order, &extension, &signature, &recovery_id), escrow_address);
+        assert_eq!(token.balance(&escrow_address), 1000);
+        assert_eq!(token.balance(&immutables.maker), 0);
+        assert_eq!(escrow_src::EscrowSrcClient::new(&s.env, &escrow_address).get_state(), State::Active);
+
+        // The order can't fund a second escrow, whoever takes it
+        assert!(s.client.is_order_filled(&immutables.order_hash));
+        s.env.mock_all_auths();
+        token.approve(&immutables.maker, &s.contract_id, &immutables.amount, &1000);
+        let mut again = immutables.clone();
+        again.taker = Address::generate(&s.env);
+        let result = s.client.try_createsrc_signed(&again, &order, &extension, &signature, &recovery_id);
+        assert_eq!(result, Err(Ok(Error::OrderAlreadyFilled)));
+    }
+
+    #[test]
+    fn test_create_src_escrow_signed_invalid() {
+        let s = setup();
+        s.env.mock_all_auths();
+
+        let (signing_key, evm_address) = evm_signer(&s.env, 1);
+        let (other_key, other_address) = evm_signer(&s.env, 2);
+        let domain_separator = eip712::domain_separator(&s.env, 1, &BytesN::from_array(&s.env, &[0x11; 20]));
+        let (immutables, order, extension) = signed_order(&s, &domain_separator, &evm_address);
+        prepare_escrow(&s, &immutables);
+        let token = TokenClient::new(&s.env, &immutables.token);
+        token.approve(&immutables.maker, &s.contract_id, &immutables.amount, &1000);
+        s.client.bind_evm_address(&immutables.maker, &evm_address);
+        let (signature, recovery_id) = sign(&s.env, &signing_key, &immutables.order_hash);
+
+        // No domain configured yet
+        let result = s.client.try_createsrc_signed(&immutables, &order, &extension, &signature, &recovery_id);
+        assert_eq!(result, Err(Ok(Error::NotInitialized)));
+        set_domain(&s);
+
+        // The order hash must be recomputed from the submitted order
+        let mut altered = order.clone();
+        altered.taking_amount = U256::from_u32(&s.env, 1);
+        let result = s.client.try_createsrc_signed(&immutables, &altered, &extension, &signature, &recovery_id);
+        assert_eq!(result, Err(Ok(Error::InvalidImmutables)));
+
+        // The escrowed amount must be the order's making amount
+        let mut more = immutables.clone();
+        more.amount = 2000;
+        let result = s.client.try_createsrc_signed(&more, &order, &extension, &signature, &recovery_id);
+        assert_eq!(result, Err(Ok(Error::InvalidImmutables)));
+
+        // The rest of the immutables must match the maker asset and the signed extension
+        let mut tampered = std::vec![immutables.clone(); 7];
+        tampered[0].token = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+        tampered[1].hashlock = BytesN::from_array(&s.env, &[3u8; 32]);
+        tampered[2].dst_chain_id = 56;
+        tampered[3].safety_deposit = 1;
+        tampered[4].src_cancellation_start += 1;
+        tampered[5].fees = vec![&s.env, FeeLeg { recipient: immutables.taker.clone(), amount: FeeAmount::Bps(100) }];
+        tampered[6].allow_clawback = true;
+        for tampered in tampered {
+            let result = s.client.try_createsrc_signed(&tampered, &order, &extension, &signature, &recovery_id);
+            assert_eq!(result, Err(Ok(Error::InvalidImmutables)));
+
+            // ...even with an extension carrying the altered terms, which the salt doesn't commit to
+            let forged = extension_for(&s.env, &tampered);
+            let result = s.client.try_createsrc_signed(&tampered, &order, &forged, &signature, &recovery_id);
+            assert_eq!(result, Err(Ok(Error::InvalidImmutables)));
+        }
+
+        // Only the order's maker can sign it, even with a key bound to the same account
+        s.client.bind_evm_address(&immutables.maker, &other_address);
+        let (signature, recovery_id) = sign(&s.env, &other_key, &immutables.order_hash);
+        let result = s.client.try_createsrc_signed(&immutables, &order, &extension, &signature, &recovery_id);
+        assert_eq!(result, Err(Ok(Error::InvalidSignature)));
+
+        // Bindings can't be taken over by another maker
+        let impostor = Address::generate(&s.env);
+        assert_eq!(s.client.try_bind_evm_address(&impostor, &evm_address), Err(Ok(Error::InvalidAddress)));
+        assert_eq!(s.client.try_unbind_evm_address(&impostor, &evm_address), Err(Ok(Error::InvalidAddress)));
+        s.client.unbind_evm_address(&immutables.maker, &evm_address);
+        assert_eq!(s.client.get_evm_binding(&evm_address), None);
+    }
+}
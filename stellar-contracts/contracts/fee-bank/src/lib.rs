@@ -0,0 +1,316 @@
+#![no_std]
+use soroban_sdk::{
+    contract, contractimpl,
+    Address, Env, symbol_short,
+    token
+};
+
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+/// Balances resolvers pre-fund to pay the per-fill fee charged by the escrow factories
+#[contract]
+pub struct FeeBank;
+
+#[contractimpl]
+impl FeeBank {
+    /// Configure the bank with its admin and the token fees are paid in
+    pub fn __constructor(env: Env, admin: Address, fee_token: Address) {
+        env.storage().instance().set(&symbol_short!("admin"), &admin);
+        env.storage().instance().set(&symbol_short!("token"), &fee_token);
+    }
+
+    /// Deposit fee tokens on behalf of a resolver, returning its new balance
+    pub fn deposit(env: Env, resolver: Address, amount: i128) -> Result<i128, Error> {
+        resolver.require_auth();
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        Self::pull_funds(&env, &resolver, amount)?;
+
+        let balance = Self::balance(env.clone(), resolver.clone()) + amount;
+        Self::set_balance(&env, &resolver, balance);
+
+        env.events().publish((symbol_short!("deposit"), resolver), amount);
+
+        Ok(balance)
+    }
+
+    /// Withdraw unused fee tokens (resolver only), returning the remaining balance
+    pub fn withdraw(env: Env, resolver: Address, amount: i128) -> Result<i128, Error> {
+        resolver.require_auth();
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let balance = Self::balance(env.clone(), resolver.clone());
+        if balance < amount {
+            return Err(Error::InsufficientBalance);
+        }
+
+        // Update the balance before paying out
+        Self::set_balance(&env, &resolver, balance - amount);
+        Self::transfer_out(&env, &resolver, amount)?;
+
+        env.events().publish((symbol_short!("withdraw"), resolver), amount);
+
+        Ok(balance - amount)
+    }
+
+    /// Debit a fill fee from a resolver's balance (registered chargers only)
+    pub fn charge(env: Env, charger: Address, resolver: Address, amount: i128) -> Result<i128, Error> {
+        charger.require_auth();
+        if !Self::is_charger(env.clone(), charger.clone()) {
+            return Err(Error::NotFeeCharger);
+        }
+        if amount < 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let balance = Self::balance(env.clone(), resolver.clone());
+        if balance < amount {
+            return Err(Error::InsufficientFeeBalance);
+        }
+
+        Self::set_balance(&env, &resolver, balance - amount);
+        let collected = Self::collected(env.clone()) + amount;
+        env.storage().instance().set(&symbol_short!("collected"), &collected);
+
+        env.events().publish((symbol_short!("charged"), charger, resolver), amount);
+
+        Ok(balance - amount)
+    }
+
+    /// Allow or disallow a contract, typically an escrow factory, to charge fees (admin only)
+    pub fn set_charger(env: Env, charger: Address, allowed: bool) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        let key = (symbol_short!("charger"), charger);
+        if allowed {
+            env.storage().persistent().set(&key, &true);
+        } else {
+            env.storage().persistent().remove(&key);
+        }
+    }
+
+    /// Check whether an address may charge fees
+    pub fn is_charger(env: Env, charger: Address) -> bool {
+        env.storage().persistent().has(&(symbol_short!("charger"), charger))
+    }
+
+    /// Send the fees charged so far to `to` (admin only), returning the amount sent
+    pub fn collect(env: Env, to: Address) -> Result<i128, Error> {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        let collected = Self::collected(env.clone());
+        if collected > 0 {
+            env.storage().instance().set(&symbol_short!("collected"), &0i128);
+            Self::transfer_out(&env, &to, collected)?;
+        }
+
+        env.events().publish((symbol_short!("collect"), to), collected);
+
+        Ok(collected)
+    }
+
+    /// Get a resolver's available balance
+    pub fn balance(env: Env, resolver: Address) -> i128 {
+        env.storage().persistent()
+            .get(&(symbol_short!("balance"), resolver))
+            .unwrap_or(0)
+    }
+
+    /// Get the fees charged and not yet collected
+    pub fn collected(env: Env) -> i128 {
+        env.storage().instance().get(&symbol_short!("collected")).unwrap_or(0)
+    }
+
+    // Helper functions
+
+    fn set_balance(env: &Env, resolver: &Address, balance: i128) {
+        let key = (symbol_short!("balance"), resolver.clone());
+        if balance == 0 {
+            env.storage().persistent().remove(&key);
+        } else {
+            env.storage().persistent().set(&key, &balance);
+        }
+    }
+
+    fn fee_token(env: &Env) -> token::Client<'_> {
+        let fee_token: Address = env.storage().instance().get(&symbol_short!("token")).unwrap();
+        token::Client::new(env, &fee_token)
+    }
+
+    /// Transfer fee tokens into the bank, checking that exactly `amount` arrived
+    fn pull_funds(env: &Env, from: &Address, amount: i128) -> Result<(), Error> {
+        let token_client = Self::fee_token(env);
+        let bank = env.current_contract_address();
+        let balance_before = token_client.balance(&bank);
+
+        match token_client.try_transfer(from, &bank, &amount) {
+            Ok(Ok(())) => {}
+            _ => return Err(Error::TransferFailed),
+        }
+
+        if token_client.balance(&bank) - balance_before != amount {
+            return Err(Error::BalanceMismatch);
+        }
+        Ok(())
+    }
+
+    /// Transfer fee tokens out of the bank, checking that exactly `amount` left
+    fn transfer_out(env: &Env, to: &Address, amount: i128) -> Result<(), Error> {
+        let token_client = Self::fee_token(env);
+        let bank = env.current_contract_address();
+        let balance_before = token_client.balance(&bank);
+
+        match token_client.try_transfer(&bank, to, &amount) {
+            Ok(Ok(())) => {}
+            _ => return Err(Error::TransferFailed),
+        }
+
+        if balance_before - token_client.balance(&bank) != amount {
+            return Err(Error::BalanceMismatch);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::{
+        vec, Address, Env, IntoVal, Val, Vec,
+        testutils::{Address as _, Events as _},
+        token::{StellarAssetClient, TokenClient},
+    };
+
+    struct Setup<'a> {
+        env: Env,
+        contract_id: Address,
+        client: FeeBankClient<'a>,
+        fee_token: Address,
+        resolver: Address,
+    }
+
+    /// Fee bank with a resolver holding 1000 fee tokens
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let admin = Address::generate(&env);
+        let fee_token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+        let contract_id = env.register(FeeBank, (admin, fee_token.clone()));
+        let client = FeeBankClient::new(&env, &contract_id);
+
+        let resolver = Address::generate(&env);
+        StellarAssetClient::new(&env, &fee_token).mint(&resolver, &1000);
+
+        Setup { env, contract_id, client, fee_token, resolver }
+    }
+
+    fn token_balance(s: &Setup, id: &Address) -> i128 {
+        TokenClient::new(&s.env, &s.fee_token).balance(id)
+    }
+
+    /// Topics and data of the events the bank published in the last invocation
+    fn bank_events(s: &Setup) -> Vec<(Vec<Val>, Val)> {
+        let mut events = Vec::new(&s.env);
+        for (contract, topics, data) in s.env.events().all().iter() {
+            if contract == s.contract_id {
+                events.push_back((topics, data));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_deposit_and_withdraw() {
+        let s = setup();
+
+        assert_eq!(s.client.deposit(&s.resolver, &600), 600);
+        assert_eq!(
+            bank_events(&s),
+            vec![&s.env, ((symbol_short!("deposit"), s.resolver.clone()).into_val(&s.env), 600i128.into_val(&s.env))]
+        );
+        assert_eq!(s.client.balance(&s.resolver), 600);
+        assert_eq!(token_balance(&s, &s.contract_id), 600);
+
+        assert_eq!(s.client.withdraw(&s.resolver, &200), 400);
+        assert_eq!(
+            bank_events(&s),
+            vec![&s.env, ((symbol_short!("withdraw"), s.resolver.clone()).into_val(&s.env), 200i128.into_val(&s.env))]
+        );
+        assert_eq!(token_balance(&s, &s.resolver), 600);
+
+        assert_eq!(s.client.try_withdraw(&s.resolver, &401), Err(Ok(Error::InsufficientBalance)));
+        assert_eq!(s.client.try_deposit(&s.resolver, &0), Err(Ok(Error::InvalidAmount)));
+        assert_eq!(s.client.try_deposit(&s.resolver, &1000), Err(Ok(Error::TransferFailed)));
+    }
+
+    #[test]
+    fn test_charge() {
+        let s = setup();
+        let factory = Address::generate(&s.env);
+        s.client.deposit(&s.resolver, &100);
+
+        // Only registered chargers can debit balances
+        assert_eq!(s.client.try_charge(&factory, &s.resolver, &30), Err(Ok(Error::NotFeeCharger)));
+
+        s.client.set_charger(&factory, &true);
+        assert!(s.client.is_charger(&factory));
+        assert_eq!(s.client.charge(&factory, &s.resolver, &30), 70);
+        assert_eq!(
+            bank_events(&s),
+            vec![
+                &s.env,
+                ((symbol_short!("charged"), factory.clone(), s.resolver.clone()).into_val(&s.env), 30i128.into_val(&s.env))
+            ]
+        );
+        assert_eq!(s.client.collected(), 30);
+
+        // Fills the balance can't cover are refused
+        assert_eq!(s.client.try_charge(&factory, &s.resolver, &71), Err(Ok(Error::InsufficientFeeBalance)));
+        assert_eq!(s.client.balance(&s.resolver), 70);
+
+        // Charged fees can no longer be withdrawn by the resolver
+        assert_eq!(s.client.try_withdraw(&s.resolver, &100), Err(Ok(Error::InsufficientBalance)));
+
+        s.client.set_charger(&factory, &false);
+        assert_eq!(s.client.try_charge(&factory, &s.resolver, &10), Err(Ok(Error::NotFeeCharger)));
+    }
+
+    #[test]
+    fn test_collect() {
+        let s = setup();
+        let factory = Address::generate(&s.env);
+        let treasury = Address::generate(&s.env);
+        s.client.deposit(&s.resolver, &100);
+        s.client.set_charger(&factory, &true);
+        s.client.charge(&factory, &s.resolver, &40);
+
+        assert_eq!(s.client.collect(&treasury), 40);
+        assert_eq!(
+            bank_events(&s),
+            vec![&s.env, ((symbol_short!("collect"), treasury.clone()).into_val(&s.env), 40i128.into_val(&s.env))]
+        );
+        assert_eq!(token_balance(&s, &treasury), 40);
+        assert_eq!(s.client.collected(), 0);
+
+        // The resolver's remaining balance stays in the bank
+        assert_eq!(token_balance(&s, &s.contract_id), 60);
+    }
+
+    #[test]
+    fn test_admin_only() {
+        let s = setup();
+        let factory = Address::generate(&s.env);
+
+        s.env.set_auths(&[]);
+        assert!(s.client.try_set_charger(&factory, &true).is_err());
+        assert!(s.client.try_collect(&factory).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Dutch auction curve of Fusion orders, as in the 1inch SDK's `AuctionDetails`
+
+use soroban_sdk::{contracttype, Vec};
+
+/// Rate bumps are expressed in units of 1e-7: 10_000_000 doubles the taking amount
+pub const RATE_BUMP_DENOMINATOR: i128 = 10_000_000;
+
+/// Intermediate point of the curve, `delay` seconds after the previous one
+#[contracttype]
+#[derive(Clone)]
+pub struct AuctionPoint {
+    pub rate_bump: u32,
+    pub delay: u32,
+}
+
+/// Auction over the taking amount, from `initial_rate_bump` at `start_time` down to none after `duration`
+#[contracttype]
+#[derive(Clone)]
+pub struct AuctionDetails {
+    pub start_time: u64,
+    pub duration: u32,
+    pub initial_rate_bump: u32,
+    pub points: Vec<AuctionPoint>,
+}
+
+/// Check that the points all fall within the auction
+pub fn is_valid(details: &AuctionDetails) -> bool {
+    let mut delays: u64 = 0;
+    for point in details.points.iter() {
+        delays += point.delay as u64;
+    }
+    delays <= details.duration as u64
+}
+
+/// Rate bump at `now`, interpolated linearly between the points of the curve
+pub fn rate_bump(details: &AuctionDetails, now: u64) -> u32 {
+    let end = details.start_time + details.duration as u64;
+    if now <= details.start_time {
+        return details.initial_rate_bump;
+    }
+    if now >= end {
+        return 0;
+    }
+
+    let mut from_time = details.start_time;
+    let mut from_bump = details.initial_rate_bump;
+    for point in details.points.iter() {
+        let to_time = from_time + point.delay as u64;
+        if now < to_time {
+            return interpolate(from_time, from_bump, to_time, point.rate_bump, now);
+        }
+        from_time = to_time;
+        from_bump = point.rate_bump;
+    }
+    interpolate(from_time, from_bump, end, 0, now)
+}
+
+/// Taking amount at a rate bump, rounded up in the maker's favour
+pub fn bumped_amount(taking_amount: i128, rate_bump: u32) -> Option<i128> {
+    let numerator = taking_amount.checked_mul(RATE_BUMP_DENOMINATOR + rate_bump as i128)?;
+    Some((numerator + RATE_BUMP_DENOMINATOR - 1) / RATE_BUMP_DENOMINATOR)
+}
+
+fn interpolate(from_time: u64, from_bump: u32, to_time: u64, to_bump: u32, now: u64) -> u32 {
+    let weighted = from_bump as u128 * (to_time - now) as u128 + to_bump as u128 * (now - from_time) as u128;
+    (weighted / (to_time - from_time) as u128) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::{vec, Env};
+
+    fn details(points: Vec<AuctionPoint>) -> AuctionDetails {
+        AuctionDetails { start_time: 1000, duration: 120, initial_rate_bump: 1_000_000, points }
+    }
+
+    #[test]
+    fn test_linear_decay() {
+        let env = Env::default();
+        let details = details(Vec::new(&env));
+
+        assert_eq!(rate_bump(&details, 0), 1_000_000);
+        assert_eq!(rate_bump(&details, 1000), 1_000_000);
+        assert_eq!(rate_bump(&details, 1030), 750_000);
+        assert_eq!(rate_bump(&details, 1060), 500_000);
+        assert_eq!(rate_bump(&details, 1119), 8_333);
+        assert_eq!(rate_bump(&details, 1120), 0);
+        assert_eq!(rate_bump(&details, 5000), 0);
+    }
+
+    #[test]
+    fn test_points() {
+        let env = Env::default();
+        // Hold most of the bump for the first 40 seconds, then drop quickly and tail off
+        let details = details(vec![
+            &env,
+            AuctionPoint { rate_bump: 900_000, delay: 40 },
+            AuctionPoint { rate_bump: 100_000, delay: 20 },
+        ]);
+        assert!(is_valid(&details));
+
+        assert_eq!(rate_bump(&details, 1020), 950_000);
+        assert_eq!(rate_bump(&details, 1040), 900_000);
+        assert_eq!(rate_bump(&details, 1050), 500_000);
+        assert_eq!(rate_bump(&details, 1060), 100_000);
+        assert_eq!(rate_bump(&details, 1090), 50_000);
+        assert_eq!(rate_bump(&details, 1120), 0);
+
+        // Points past the end of the auction
+        let details = self::details(vec![&env, AuctionPoint { rate_bump: 0, delay: 121 }]);
+        assert!(!is_valid(&details));
+    }
+
+    #[test]
+    fn test_bumped_amount() {
+        assert_eq!(bumped_amount(1000, 0), Some(1000));
+        assert_eq!(bumped_amount(1000, 1_000_000), Some(1100));
+        // 1000.0001 rounds up
+        assert_eq!(bumped_amount(1000, 1), Some(1001));
+        assert_eq!(bumped_amount(i128::MAX, 1), None);
+    }
+}
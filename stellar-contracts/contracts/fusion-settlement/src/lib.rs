@@ -0,0 +1,381 @@
+#![no_std]
+use soroban_sdk::{
+    contract, contractimpl, contracttype,
+    xdr::ToXdr, Address, BytesN, Env, IntoVal, symbol_short,
+    token
+};
+
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+mod auction;
+
+pub use auction::{AuctionDetails, AuctionPoint};
+
+/// Approximate number of ledgers closed per day
+const DAY_IN_LEDGERS: u32 = 17_280;
+
+/// Fill progress is extended to live this many ledgers ahead...
+const ORDER_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+
+/// ...whenever it is touched with less than this left
+const ORDER_LIFETIME_THRESHOLD: u32 = ORDER_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+/// Maker order for a swap between two Stellar assets, priced by a Dutch auction
+///
+/// The maker sells `making_amount` of `maker_asset` for at least `taking_amount` of
+/// `taker_asset`, plus the auction's rate bump at the time of each fill.
+#[contracttype]
+#[derive(Clone)]
+pub struct Order {
+    pub salt: BytesN<32>,               // Distinguishes otherwise identical orders
+    pub maker: Address,
+    pub receiver: Option<Address>,      // Paid the taking amount instead of the maker, if set
+    pub maker_asset: Address,
+    pub taker_asset: Address,
+    pub making_amount: i128,
+    pub taking_amount: i128,            // Lowest price, reached at the end of the auction
+    pub expiration: u64,                // Last timestamp the order can be filled at
+    pub auction: AuctionDetails,
+}
+
+/// Settles signed maker orders against resolver liquidity, with no hashlock or timelocks
+///
+/// Resolvers are whitelisted as for cross-chain orders. The maker authorizes an order
+/// once, by its hash, and lets this contract spend the maker asset; each fill then swaps
+/// the maker's tokens for the resolver's at the current auction price atomically.
+#[contract]
+pub struct FusionSettlement;
+
+#[contractimpl]
+impl FusionSettlement {
+    /// Configure the settlement contract with its admin
+    pub fn __constructor(env: Env, admin: Address) {
+        env.storage().instance().set(&symbol_short!("admin"), &admin);
+    }
+
+    /// Allow or disallow a resolver to settle orders (admin only)
+    pub fn set_resolver(env: Env, resolver: Address, allowed: bool) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        let key = (symbol_short!("resolver"), resolver);
+        if allowed {
+            env.storage().persistent().set(&key, &true);
+        } else {
+            env.storage().persistent().remove(&key);
+        }
+    }
+
+    /// Check whether a resolver may settle orders
+    pub fn is_resolver(env: Env, resolver: Address) -> bool {
+        env.storage().persistent().has(&(symbol_short!("resolver"), resolver))
+    }
+
+    /// Fill `making_amount` of an order at the current auction price, returning the taking amount paid
+    ///
+    /// The first fill of an order needs the maker's authorization of `settle` for the
+    /// order hash; later fills only need the resolver's.
+    pub fn settle(env: Env, resolver: Address, order: Order, making_amount: i128) -> Result<i128, Error> {
+        resolver.require_auth();
+        if !Self::is_resolver(env.clone(), resolver.clone()) {
+            return Err(Error::AuthNotAllowed);
+        }
+
+        let order_hash = Self::hash_order(env.clone(), order.clone());
+        let remaining = match Self::get_remaining(env.clone(), order_hash.clone()) {
+            Some(remaining) => remaining,
+            None => {
+                Self::validate_order(&order)?;
+                order.maker.require_auth_for_args((order_hash.clone(),).into_val(&env));
+                order.making_amount
+            }
+        };
+
+        let now = env.ledger().timestamp();
+        if now > order.expiration {
+            return Err(Error::WindowClosed);
+        }
+        if making_amount <= 0 || making_amount > remaining {
+            return Err(Error::InvalidAmount);
+        }
+
+        let taking_amount = Self::taking_amount(&order, making_amount, now).ok_or(Error::InvalidAmount)?;
+        Self::set_remaining(&env, &order_hash, remaining - making_amount);
+
+        let receiver = order.receiver.clone().unwrap_or(order.maker.clone());
+        Self::transfer_checked(&env, &order.maker_asset, &order.maker, &resolver, making_amount, true)?;
+        Self::transfer_checked(&env, &order.taker_asset, &resolver, &receiver, taking_amount, false)?;
+
+        env.events().publish((symbol_short!("settled"), order_hash, resolver), (making_amount, taking_amount));
+
+        Ok(taking_amount)
+    }
+
+    /// Cancel an order, filled or not (maker only)
+    pub fn cancel_order(env: Env, order: Order) {
+        order.maker.require_auth();
+
+        let order_hash = Self::hash_order(env.clone(), order);
+        Self::set_remaining(&env, &order_hash, 0);
+
+        env.events().publish((symbol_short!("cancel"), order_hash), ());
+    }
+
+    /// Get the making amount left to fill, if the order has been filled or cancelled before
+    pub fn get_remaining(env: Env, order_hash: BytesN<32>) -> Option<i128> {
+        env.storage().persistent().get(&(symbol_short!("remain"), order_hash))
+    }
+
+    /// Get the taking amount a fill of `making_amount` pays at the current time
+    pub fn get_taking_amount(env: Env, order: Order, making_amount: i128) -> Result<i128, Error> {
+        Self::taking_amount(&order, making_amount, env.ledger().timestamp()).ok_or(Error::InvalidAmount)
+    }
+
+    /// Compute the hash the maker authorizes an order by
+    pub fn hash_order(env: Env, order: Order) -> BytesN<32> {
+        env.crypto().sha256(&order.to_xdr(&env)).into()
+    }
+
+    // Helper functions
+
+    fn validate_order(order: &Order) -> Result<(), Error> {
+        if order.making_amount <= 0 || order.taking_amount <= 0 || !auction::is_valid(&order.auction) {
+            return Err(Error::InvalidImmutables);
+        }
+        Ok(())
+    }
+
+    /// Pro-rata share of the order's taking amount, bumped by the auction, both rounded up
+    fn taking_amount(order: &Order, making_amount: i128, now: u64) -> Option<i128> {
+        let share = making_amount.checked_mul(order.taking_amount)?;
+        let share = (share + order.making_amount - 1) / order.making_amount;
+        auction::bumped_amount(share, auction::rate_bump(&order.auction, now))
+    }
+
+    fn set_remaining(env: &Env, order_hash: &BytesN<32>, remaining: i128) {
+        let key = (symbol_short!("remain"), order_hash.clone());
+        env.storage().persistent().set(&key, &remaining);
+        env.storage().persistent().extend_ttl(&key, ORDER_LIFETIME_THRESHOLD, ORDER_BUMP_AMOUNT);
+    }
+
+    /// Transfer tokens, checking that exactly `amount` arrived (same as EscrowSrcFactory)
+    ///
+    /// With `from_allowance` the tokens are pulled from the allowance `from` gave this contract.
+    /// Fee-on-transfer and rebasing tokens deliver a different amount and are rejected.
+    fn transfer_checked(
+        env: &Env,
+        token: &Address,
+        from: &Address,
+        to: &Address,
+        amount: i128,
+        from_allowance: bool,
+    ) -> Result<(), Error> {
+        let token_client = token::Client::new(env, token);
+        let balance_before = token_client.balance(to);
+
+        let result = if from_allowance {
+            token_client.try_transfer_from(&env.current_contract_address(), from, to, &amount)
+        } else {
+            token_client.try_transfer(from, to, &amount)
+        };
+        match result {
+            Ok(Ok(())) => {}
+            _ => return Err(Error::TransferFailed),
+        }
+
+        if token_client.balance(to) - balance_before != amount {
+            return Err(Error::BalanceMismatch);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::{
+        testutils::{Address as _, Events as _, Ledger, MockAuth, MockAuthInvoke},
+        token::{StellarAssetClient, TokenClient},
+        vec, Address, Env, Val, Vec,
+    };
+
+    struct Setup<'a> {
+        env: Env,
+        client: FusionSettlementClient<'a>,
+        maker: Address,
+        resolver: Address,
+        maker_asset: Address,
+        taker_asset: Address,
+    }
+
+    /// Maker holding and approving 1000 of the maker asset, whitelisted resolver holding 5000 of the taker asset
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let contract_id = env.register(FusionSettlement, (Address::generate(&env),));
+        let client = FusionSettlementClient::new(&env, &contract_id);
+
+        let maker = Address::generate(&env);
+        let resolver = Address::generate(&env);
+        client.set_resolver(&resolver, &true);
+
+        let maker_asset = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+        let taker_asset = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+        StellarAssetClient::new(&env, &maker_asset).mint(&maker, &1000);
+        StellarAssetClient::new(&env, &taker_asset).mint(&resolver, &5000);
+        TokenClient::new(&env, &maker_asset).approve(&maker, &contract_id, &1000, &1000);
+
+        Setup { env, client, maker, resolver, maker_asset, taker_asset }
+    }
+
+    /// 1000 for at least 2000, with a 10% bump decaying linearly from t=100 to t=220
+    fn order(s: &Setup) -> Order {
+        Order {
+            salt: BytesN::from_array(&s.env, &[1u8; 32]),
+            maker: s.maker.clone(),
+            receiver: None,
+            maker_asset: s.maker_asset.clone(),
+            taker_asset: s.taker_asset.clone(),
+            making_amount: 1000,
+            taking_amount: 2000,
+            expiration: 1000,
+            auction: AuctionDetails { start_time: 100, duration: 120, initial_rate_bump: 1_000_000, points: Vec::new(&s.env) },
+        }
+    }
+
+    fn set_time(env: &Env, timestamp: u64) {
+        env.ledger().with_mut(|li| li.timestamp = timestamp);
+    }
+
+    fn balance(s: &Setup, token: &Address, id: &Address) -> i128 {
+        TokenClient::new(&s.env, token).balance(id)
+    }
+
+    /// Topics and data of the events the settlement published in the last invocation
+    fn settlement_events(s: &Setup) -> Vec<(Vec<Val>, Val)> {
+        let mut events = Vec::new(&s.env);
+        for (contract, topics, data) in s.env.events().all().iter() {
+            if contract == s.client.address {
+                events.push_back((topics, data));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_settle() {
+        let s = setup();
+        let order = order(&s);
+
+        // Halfway through the auction the bump is 5%
+        set_time(&s.env, 160);
+        assert_eq!(s.client.get_taking_amount(&order, &1000), 2100);
+        let order_hash = s.client.hash_order(&order);
+        assert_eq!(s.client.settle(&s.resolver, &order, &1000), 2100);
+        assert_eq!(
+            settlement_events(&s),
+            vec![
+                &s.env,
+                (
+                    (symbol_short!("settled"), order_hash.clone(), s.resolver.clone()).into_val(&s.env),
+                    (1000i128, 2100i128).into_val(&s.env)
+                )
+            ]
+        );
+
+        assert_eq!(balance(&s, &s.maker_asset, &s.resolver), 1000);
+        assert_eq!(balance(&s, &s.maker_asset, &s.maker), 0);
+        assert_eq!(balance(&s, &s.taker_asset, &s.maker), 2100);
+        assert_eq!(balance(&s, &s.taker_asset, &s.resolver), 2900);
+
+        assert_eq!(s.client.get_remaining(&order_hash), Some(0));
+        assert_eq!(s.client.try_settle(&s.resolver, &order, &1), Err(Ok(Error::InvalidAmount)));
+    }
+
+    #[test]
+    fn test_partial_fills_follow_the_auction() {
+        let s = setup();
+        let mut order = order(&s);
+        let receiver = Address::generate(&s.env);
+        order.receiver = Some(receiver.clone());
+
+        // Before the auction starts the full bump applies
+        set_time(&s.env, 50);
+        assert_eq!(s.client.settle(&s.resolver, &order, &400), 880);
+
+        // Later fills need no new maker authorization
+        s.env.set_auths(&[]);
+        set_time(&s.env, 300);
+        s.env.mock_auths(&[MockAuth {
+            address: &s.resolver,
+            invoke: &MockAuthInvoke {
+                contract: &s.client.address,
+                fn_name: "settle",
+                args: (s.resolver.clone(), order.clone(), 600i128).into_val(&s.env),
+                sub_invokes: &[MockAuthInvoke {
+                    contract: &s.taker_asset,
+                    fn_name: "transfer",
+                    args: (s.resolver.clone(), receiver.clone(), 1200i128).into_val(&s.env),
+                    sub_invokes: &[],
+                }],
+            },
+        }]);
+        // After the auction ends only the minimum is paid
+        assert_eq!(s.client.settle(&s.resolver, &order, &600), 1200);
+
+        assert_eq!(balance(&s, &s.taker_asset, &receiver), 2080);
+        assert_eq!(balance(&s, &s.maker_asset, &s.resolver), 1000);
+    }
+
+    #[test]
+    fn test_first_fill_requires_maker_auth() {
+        let s = setup();
+        let order = order(&s);
+        let order_hash = s.client.hash_order(&order);
+
+        s.env.set_auths(&[]);
+        assert!(s.client.try_settle(&s.resolver, &order, &1000).is_err());
+        assert_eq!(s.client.get_remaining(&order_hash), None);
+    }
+
+    #[test]
+    fn test_settle_restrictions() {
+        let s = setup();
+        let order = order(&s);
+
+        let stranger = Address::generate(&s.env);
+        assert_eq!(s.client.try_settle(&stranger, &order, &1000), Err(Ok(Error::AuthNotAllowed)));
+
+        let mut invalid = order.clone();
+        invalid.auction.points = vec![&s.env, AuctionPoint { rate_bump: 0, delay: 121 }];
+        assert_eq!(s.client.try_settle(&s.resolver, &invalid, &1000), Err(Ok(Error::InvalidImmutables)));
+
+        set_time(&s.env, 1001);
+        assert_eq!(s.client.try_settle(&s.resolver, &order, &1000), Err(Ok(Error::WindowClosed)));
+        set_time(&s.env, 0);
+
+        assert_eq!(s.client.try_settle(&s.resolver, &order, &1001), Err(Ok(Error::InvalidAmount)));
+
+        // A cancelled order cannot be filled, even if it never was
+        s.client.cancel_order(&order);
+        assert_eq!(
+            settlement_events(&s),
+            vec![&s.env, ((symbol_short!("cancel"), s.client.hash_order(&order)).into_val(&s.env), ().into_val(&s.env))]
+        );
+        assert_eq!(s.client.try_settle(&s.resolver, &order, &1000), Err(Ok(Error::InvalidAmount)));
+        assert_eq!(balance(&s, &s.maker_asset, &s.maker), 1000);
+    }
+
+    #[test]
+    fn test_resolver_without_liquidity() {
+        let s = setup();
+        let order = order(&s);
+        let poor = Address::generate(&s.env);
+        s.client.set_resolver(&poor, &true);
+
+        assert_eq!(s.client.try_settle(&poor, &order, &1000), Err(Ok(Error::TransferFailed)));
+        assert_eq!(balance(&s, &s.maker_asset, &s.maker), 1000);
+    }
+}
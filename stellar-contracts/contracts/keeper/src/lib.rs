@@ -0,0 +1,281 @@
+#![no_std]
+use soroban_sdk::{
+    contract, contractimpl, contracttype, contractclient,
+    Address, BytesN, Env, Vec, symbol_short,
+    token, InvokeError
+};
+
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+/// Outcome of a single escrow call in a batch
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The call succeeded, with the safety deposit (native XLM) the caller received
+    Done(i128),
+    /// The call failed with this error code; 0 if the escrow did not return one
+    Failed(u32),
+}
+
+/// Per-escrow outcomes of a batch, in input order, and the total safety deposit earned
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BatchResult {
+    pub outcomes: Vec<Outcome>,
+    pub total_safety_deposit: i128,
+}
+
+/// Public actions of the escrow contracts used by the keeper
+#[contractclient(name = "EscrowClient")]
+pub trait EscrowInterface {
+    fn public_withdraw(env: Env, secret: BytesN<32>, caller: Address) -> Result<(), Error>;
+    fn public_cancel(env: Env, caller: Address) -> Result<(), Error>;
+}
+
+/// Runs public withdrawals and cancellations across many escrows in one transaction
+#[contract]
+pub struct Keeper;
+
+#[contractimpl]
+impl Keeper {
+    /// Configure the keeper with the native token safety deposits are paid in
+    pub fn __constructor(env: Env, native_token: Address) {
+        env.storage().instance().set(&symbol_short!("native"), &native_token);
+    }
+
+    /// Publicly withdraw each `(escrow, secret)`, skipping escrows whose call fails
+    pub fn batch_public_withdraw(env: Env, caller: Address, escrows: Vec<(Address, BytesN<32>)>) -> BatchResult {
+        caller.require_auth();
+
+        let mut calls = Vec::new(&env);
+        for (escrow, secret) in escrows.iter() {
+            calls.push_back((escrow, Some(secret)));
+        }
+        Self::run(&env, &caller, calls)
+    }
+
+    /// Publicly cancel each escrow, skipping escrows whose call fails
+    pub fn batch_public_cancel(env: Env, caller: Address, escrows: Vec<Address>) -> BatchResult {
+        caller.require_auth();
+
+        let mut calls = Vec::new(&env);
+        for escrow in escrows.iter() {
+            calls.push_back((escrow, None));
+        }
+        Self::run(&env, &caller, calls)
+    }
+
+    /// Get the native token address
+    pub fn get_native_token(env: Env) -> Address {
+        env.storage().instance().get(&symbol_short!("native")).unwrap()
+    }
+
+    // Helper functions
+
+    /// Call each escrow (withdraw when a secret is given, cancel otherwise) and collect the outcomes
+    fn run(env: &Env, caller: &Address, calls: Vec<(Address, Option<BytesN<32>>)>) -> BatchResult {
+        let native = token::Client::new(env, &Self::get_native_token(env.clone()));
+        let mut outcomes = Vec::new(env);
+        let mut total_safety_deposit = 0i128;
+
+        for (escrow, secret) in calls.iter() {
+            let client = EscrowClient::new(env, &escrow);
+            let balance_before = native.balance(caller);
+
+            // A failed call is rolled back on its own and does not abort the batch
+            let result = match &secret {
+                Some(secret) => client.try_public_withdraw(secret, caller),
+                None => client.try_public_cancel(caller),
+            };
+
+            let outcome = match result {
+                Ok(Ok(())) => {
+                    let earned = native.balance(caller) - balance_before;
+                    total_safety_deposit += earned;
+                    Outcome::Done(earned)
+                }
+                Err(Ok(e)) => Outcome::Failed(e as u32),
+                Err(Err(InvokeError::Contract(code))) => Outcome::Failed(code),
+                _ => Outcome::Failed(0),
+            };
+
+            if let Outcome::Failed(code) = outcome {
+                env.events().publish((symbol_short!("failed"), escrow), code);
+            }
+            outcomes.push_back(outcome);
+        }
+
+        env.events().publish((symbol_short!("batch"), caller.clone()), (outcomes.len(), total_safety_deposit));
+
+        BatchResult { outcomes, total_safety_deposit }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use escrow_src::{EscrowSrc, EscrowSrcClient, Immutables, State, TimeBase};
+    use soroban_sdk::{
+        testutils::{Address as _, Events as _, Ledger},
+        token::{StellarAssetClient, TokenClient},
+        vec, Address, Bytes, Env, IntoVal, Val,
+    };
+
+    struct Setup<'a> {
+        env: Env,
+        client: KeeperClient<'a>,
+        native_token: Address,
+        token: Address,
+        caller: Address,
+    }
+
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let native_token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+        let token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+        let contract_id = env.register(Keeper, (native_token.clone(),));
+        let client = KeeperClient::new(&env, &contract_id);
+        let caller = Address::generate(&env);
+
+        Setup { env, client, native_token, token, caller }
+    }
+
+    /// Register and initialize a funded source escrow, returning it with its secret
+    fn create_escrow(s: &Setup, n: u8, safety_deposit: i128) -> (Address, BytesN<32>) {
+        let secret = BytesN::from_array(&s.env, &[n; 32]);
+        let deployer = Address::generate(&s.env);
+        let salt = BytesN::from_array(&s.env, &[n; 32]);
+        let address = s.env.deployer().with_address(deployer.clone(), salt.clone()).deployed_address();
+        let escrow = s.env.register_at(&address, EscrowSrc, ());
+
+        let immutables = Immutables {
+            order_hash: BytesN::from_array(&s.env, &[n; 32]),
+            hashlock: s.env.crypto().sha256(&Bytes::from_slice(&s.env, &secret.to_array())).into(),
+            maker: Address::generate(&s.env),
+            taker: Address::generate(&s.env),
+            token: s.token.clone(),
+            amount: 1000,
+            safety_deposit,
+            src_chain_id: 1000,
+            dst_chain_id: 1,
+            time_base: TimeBase::Timestamp,
+            deployed_at: 0,
+            src_withdrawal_start: 60,
+            src_public_withdrawal_start: 120,
+            src_cancellation_start: 300,
+            src_public_cancellation_start: 600,
+            dst_withdrawal_start: 60,
+            dst_public_withdrawal_start: 120,
+            dst_cancellation_start: 300,
+            allow_clawback: false,
+            fees: Vec::new(&s.env),
+        };
+
+        StellarAssetClient::new(&s.env, &s.token).mint(&escrow, &immutables.amount);
+        StellarAssetClient::new(&s.env, &s.native_token).mint(&escrow, &safety_deposit);
+        EscrowSrcClient::new(&s.env, &escrow).init(&deployer, &salt, &immutables, &s.native_token);
+
+        (escrow, secret)
+    }
+
+    /// Topics and data of the events the keeper published in the last invocation
+    fn keeper_events(s: &Setup) -> Vec<(Vec<Val>, Val)> {
+        let mut events = Vec::new(&s.env);
+        for (contract, topics, data) in s.env.events().all().iter() {
+            if contract == s.client.address {
+                events.push_back((topics, data));
+            }
+        }
+        events
+    }
+
+    fn set_time(env: &Env, timestamp: u64) {
+        env.ledger().with_mut(|li| {
+            li.timestamp = timestamp;
+        });
+    }
+
+    #[test]
+    fn test_batch_public_withdraw() {
+        let s = setup();
+        let (first, secret) = create_escrow(&s, 1, 100);
+        let (second, _) = create_escrow(&s, 2, 50);
+        set_time(&s.env, 150);
+
+        let wrong_secret = BytesN::from_array(&s.env, &[9u8; 32]);
+        let result = s.client.batch_public_withdraw(
+            &s.caller,
+            &vec![&s.env, (first.clone(), secret), (second.clone(), wrong_secret)],
+        );
+        assert_eq!(
+            keeper_events(&s),
+            vec![
+                &s.env,
+                ((symbol_short!("failed"), second.clone()).into_val(&s.env), (Error::InvalidSecret as u32).into_val(&s.env)),
+                ((symbol_short!("batch"), s.caller.clone()).into_val(&s.env), (2u32, 100i128).into_val(&s.env)),
+            ]
+        );
+
+        // The bad secret is skipped without undoing the other withdrawal
+        assert_eq!(result.outcomes, vec![&s.env, Outcome::Done(100), Outcome::Failed(Error::InvalidSecret as u32)]);
+        assert_eq!(result.total_safety_deposit, 100);
+        assert_eq!(TokenClient::new(&s.env, &s.native_token).balance(&s.caller), 100);
+        assert_eq!(EscrowSrcClient::new(&s.env, &first).get_state(), State::Withdrawn);
+        assert_eq!(EscrowSrcClient::new(&s.env, &second).get_state(), State::Active);
+    }
+
+    #[test]
+    fn test_batch_public_cancel() {
+        let s = setup();
+        let (first, _) = create_escrow(&s, 1, 100);
+        let (second, _) = create_escrow(&s, 2, 50);
+        let (third, _) = create_escrow(&s, 3, 25);
+        let not_an_escrow = Address::generate(&s.env);
+        set_time(&s.env, 700);
+
+        // Already cancelled by someone else
+        EscrowSrcClient::new(&s.env, &second).public_cancel(&Address::generate(&s.env));
+
+        let result = s.client.batch_public_cancel(
+            &s.caller,
+            &vec![&s.env, first, second, not_an_escrow, third],
+        );
+
+        assert_eq!(
+            result.outcomes,
+            vec![
+                &s.env,
+                Outcome::Done(100),
+                Outcome::Failed(Error::AlreadyCancelled as u32),
+                Outcome::Failed(0),
+                Outcome::Done(25),
+            ]
+        );
+        assert_eq!(result.total_safety_deposit, 125);
+        assert_eq!(TokenClient::new(&s.env, &s.native_token).balance(&s.caller), 125);
+    }
+
+    #[test]
+    fn test_batch_too_early() {
+        let s = setup();
+        let (escrow, _) = create_escrow(&s, 1, 100);
+        set_time(&s.env, 150);
+
+        let result = s.client.batch_public_cancel(&s.caller, &vec![&s.env, escrow]);
+        assert_eq!(result.outcomes, vec![&s.env, Outcome::Failed(Error::TooEarlyForPublicCancellation as u32)]);
+        assert_eq!(result.total_safety_deposit, 0);
+    }
+
+    #[test]
+    fn test_batch_requires_caller_auth() {
+        let s = setup();
+        let (escrow, _) = create_escrow(&s, 1, 100);
+        set_time(&s.env, 700);
+
+        s.env.set_auths(&[]);
+        assert!(s.client.try_batch_public_cancel(&s.caller, &vec![&s.env, escrow]).is_err());
+    }
+}
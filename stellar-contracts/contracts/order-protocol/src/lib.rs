@@ -0,0 +1,586 @@
+#![no_std]
+use soroban_sdk::{
+    contract, contractclient, contractimpl, contracttype,
+    xdr::ToXdr, Address, BytesN, Env, Vec, symbol_short,
+    token
+};
+
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+/// Approximate number of ledgers closed per day
+const DAY_IN_LEDGERS: u32 = 17_280;
+
+/// Orders are extended to live this many ledgers ahead...
+const ORDER_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+
+/// ...whenever they are touched with less than this left
+const ORDER_LIFETIME_THRESHOLD: u32 = ORDER_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+/// Maximum number of parts a partially fillable order can be split into
+pub const MAX_PARTS: u32 = 16;
+
+/// Size of a fee leg, either fixed or in basis points of the escrowed amount (same as the escrow contracts)
+#[contracttype]
+#[derive(Clone)]
+pub enum FeeAmount {
+    Fixed(i128),
+    Bps(u32),
+}
+
+/// Part of the withdrawn tokens paid to a protocol or integrator (same as the escrow contracts)
+#[contracttype]
+#[derive(Clone)]
+pub struct FeeLeg {
+    pub recipient: Address,
+    pub amount: FeeAmount,
+}
+
+/// Clock the timelocks of an escrow count in (same as EscrowSrcFactory)
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeBase {
+    Timestamp,                          // Seconds of ledger close time
+    Sequence,                           // Ledger sequence numbers
+}
+
+/// Immutable parameters for a source escrow (same as EscrowSrcFactory)
+#[contracttype]
+#[derive(Clone)]
+pub struct Immutables {
+    pub order_hash: BytesN<32>,
+    pub hashlock: BytesN<32>,
+    pub maker: Address,
+    pub taker: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub safety_deposit: i128,
+    pub src_chain_id: u64,              // Chain the maker's tokens are locked on
+    pub dst_chain_id: u64,              // Chain the taker's tokens are paid out on
+    pub time_base: TimeBase,            // Clock the timelocks and deployed_at count in
+    pub deployed_at: u64,
+    // Timelock durations from deployment, in seconds or ledgers per time_base
+    pub src_withdrawal_start: u32,      // When taker can withdraw
+    pub src_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub src_cancellation_start: u32,     // When taker can cancel
+    pub src_public_cancellation_start: u32, // When anyone can cancel
+    pub dst_withdrawal_start: u32,      // When taker can withdraw
+    pub dst_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub dst_cancellation_start: u32,     // When taker can cancel
+    pub allow_clawback: bool,           // Order accepts tokens the issuer can claw back
+    pub fees: Vec<FeeLeg>,              // Paid out of the amount on withdrawal, at most MAX_FEE_LEGS
+}
+
+/// Maker order for a cross-chain swap out of Stellar
+///
+/// Each fill escrows its share of the making amount under the order's escrow terms.
+#[contracttype]
+#[derive(Clone)]
+pub struct Order {
+    pub salt: BytesN<32>,               // Distinguishes otherwise identical orders
+    pub maker: Address,
+    pub token: Address,
+    pub making_amount: i128,
+    pub expiration: u64,                // Last timestamp the order can be filled at
+    pub src_chain_id: u64,              // Chain of this protocol, checked by the factory
+    pub dst_chain_id: u64,              // Chain the maker is paid out on
+    // One hashlock for an order filled at once; parts + 1 for an order filled in up to `parts` parts
+    pub hashlocks: Vec<BytesN<32>>,
+    // Escrow terms, from each escrow's deployment in the time base's units
+    pub time_base: TimeBase,
+    pub src_withdrawal_start: u32,
+    pub src_public_withdrawal_start: u32,
+    pub src_cancellation_start: u32,
+    pub src_public_cancellation_start: u32,
+    pub dst_withdrawal_start: u32,
+    pub dst_public_withdrawal_start: u32,
+    pub dst_cancellation_start: u32,
+    pub allow_clawback: bool,
+    pub fees: Vec<FeeLeg>,              // Charged on each fill's escrow
+}
+
+/// Fill progress of a placed order
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OrderState {
+    pub remaining: i128,                // Making amount not filled yet
+    pub next_part: u32,                 // Lowest hashlock index the next fill may use
+}
+
+/// Interface of the EscrowSrcFactory
+#[contractclient(name = "EscrowSrcFactoryClient")]
+pub trait EscrowSrcFactoryInterface {
+    fn post_interaction(env: Env, src_immutables: Immutables) -> Result<Address, Error>;
+    fn compute_escrow_address(env: Env, immutables: Immutables) -> Address;
+}
+
+/// Holds maker orders and lets whitelisted resolvers fill them into source escrows
+///
+/// A fill sends the maker's tokens, from the allowance the maker gave this contract,
+/// and the resolver's safety deposit straight to the escrow address, then has
+/// EscrowSrcFactory create the escrow as its post-interaction.
+#[contract]
+pub struct OrderProtocol;
+
+#[contractimpl]
+impl OrderProtocol {
+    /// Configure the protocol with its admin, the source factory and the native XLM asset contract
+    pub fn __constructor(env: Env, admin: Address, src_factory: Address, native_token: Address) {
+        env.storage().instance().set(&symbol_short!("admin"), &admin);
+        env.storage().instance().set(&symbol_short!("srcfact"), &src_factory);
+        env.storage().instance().set(&symbol_short!("native"), &native_token);
+    }
+
+    /// Allow or disallow a resolver to fill orders (admin only)
+    pub fn set_resolver(env: Env, resolver: Address, allowed: bool) {
+        let admin: Address = env.storage().instance().get(&symbol_short!("admin")).unwrap();
+        admin.require_auth();
+
+        let key = (symbol_short!("resolver"), resolver);
+        if allowed {
+            env.storage().persistent().set(&key, &true);
+        } else {
+            env.storage().persistent().remove(&key);
+        }
+    }
+
+    /// Check whether a resolver may fill orders
+    pub fn is_resolver(env: Env, resolver: Address) -> bool {
+        env.storage().persistent().has(&(symbol_short!("resolver"), resolver))
+    }
+
+    /// Place an order (maker only), returning its hash
+    ///
+    /// The maker must also let this contract spend the making amount of the token.
+    pub fn place_order(env: Env, order: Order) -> Result<BytesN<32>, Error> {
+        order.maker.require_auth();
+
+        let parts = order.hashlocks.len();
+        if order.making_amount <= 0 || parts == 0 || parts > MAX_PARTS + 1 {
+            return Err(Error::InvalidImmutables);
+        }
+
+        let order_hash = Self::hash_order(env.clone(), order.clone());
+        let key = (symbol_short!("order"), order_hash.clone());
+        if env.storage().persistent().has(&key) {
+            return Err(Error::AlreadyInitialized);
+        }
+
+        env.storage().persistent().set(&key, &order);
+        Self::set_state(&env, &order_hash, &OrderState { remaining: order.making_amount, next_part: 0 });
+
+        env.events().publish((symbol_short!("placed"), order_hash.clone(), order.maker), order.making_amount);
+
+        Ok(order_hash)
+    }
+
+    /// Cancel the unfilled remainder of an order (maker only)
+    pub fn cancel_order(env: Env, order_hash: BytesN<32>) -> Result<(), Error> {
+        let order = Self::get_order(env.clone(), order_hash.clone()).ok_or(Error::OrderNotFound)?;
+        order.maker.require_auth();
+
+        let mut state = Self::get_order_state(env.clone(), order_hash.clone()).ok_or(Error::OrderNotFound)?;
+        let cancelled = state.remaining;
+        state.remaining = 0;
+        Self::set_state(&env, &order_hash, &state);
+
+        env.events().publish((symbol_short!("cancel"), order_hash), cancelled);
+
+        Ok(())
+    }
+
+    /// Fill `amount` of an order into a new source escrow the resolver is the taker of
+    ///
+    /// Orders with several hashlocks can be filled in parts: a fill that brings the
+    /// filled amount into the i-th of `parts` equal shares uses hashlock i, and the
+    /// fill completing the order uses the last one. Each hashlock is used at most once.
+    pub fn fill_order(
+        env: Env,
+        resolver: Address,
+        order_hash: BytesN<32>,
+        amount: i128,
+        safety_deposit: i128,
+    ) -> Result<Address, Error> {
+        resolver.require_auth();
+        if !Self::is_resolver(env.clone(), resolver.clone()) {
+            return Err(Error::AuthNotAllowed);
+        }
+
+        let order = Self::get_order(env.clone(), order_hash.clone()).ok_or(Error::OrderNotFound)?;
+        let mut state = Self::get_order_state(env.clone(), order_hash.clone()).ok_or(Error::OrderNotFound)?;
+        if env.ledger().timestamp() > order.expiration {
+            return Err(Error::WindowClosed);
+        }
+        if amount <= 0 || amount > state.remaining || safety_deposit < 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let part = Self::fill_part(&order, &state, amount)?;
+        state.remaining -= amount;
+        state.next_part = part + 1;
+        Self::set_state(&env, &order_hash, &state);
+
+        let immutables = Self::fill_immutables(&order, &order_hash, part, &resolver, amount, safety_deposit);
+        let factory = EscrowSrcFactoryClient::new(&env, &Self::src_factory(&env));
+        let escrow_address = factory.compute_escrow_address(&immutables);
+
+        // Fund the escrow before the factory creates it, as its post-interaction checks
+        if safety_deposit > 0 {
+            let native = token::Client::new(&env, &Self::native_token(&env));
+            if native.try_transfer(&resolver, &escrow_address, &safety_deposit).is_err() {
+                return Err(Error::InsufficientBalance);
+            }
+        }
+        let token_client = token::Client::new(&env, &order.token);
+        if token_client
+            .try_transfer_from(&env.current_contract_address(), &order.maker, &escrow_address, &amount)
+            .is_err()
+        {
+            return Err(Error::TransferFailed);
+        }
+
+        // Factory errors share the protocol's codes and are passed through as-is
+        let escrow = match factory.try_post_interaction(&immutables) {
+            Ok(Ok(escrow)) => escrow,
+            Err(Ok(error)) => return Err(error),
+            _ => return Err(Error::EscrowCreationFailed),
+        };
+
+        env.events().publish((symbol_short!("filled"), order_hash, resolver), (escrow.clone(), amount, state.remaining));
+
+        Ok(escrow)
+    }
+
+    /// Get a placed order
+    pub fn get_order(env: Env, order_hash: BytesN<32>) -> Option<Order> {
+        env.storage().persistent().get(&(symbol_short!("order"), order_hash))
+    }
+
+    /// Get the fill progress of a placed order
+    pub fn get_order_state(env: Env, order_hash: BytesN<32>) -> Option<OrderState> {
+        env.storage().persistent().get(&(symbol_short!("state"), order_hash))
+    }
+
+    /// Compute the hash identifying an order, used as the escrows' order hash
+    pub fn hash_order(env: Env, order: Order) -> BytesN<32> {
+        env.crypto().sha256(&order.to_xdr(&env)).into()
+    }
+
+    // Helper functions
+
+    fn src_factory(env: &Env) -> Address {
+        env.storage().instance().get(&symbol_short!("srcfact")).unwrap()
+    }
+
+    fn native_token(env: &Env) -> Address {
+        env.storage().instance().get(&symbol_short!("native")).unwrap()
+    }
+
+    fn set_state(env: &Env, order_hash: &BytesN<32>, state: &OrderState) {
+        let order_key = (symbol_short!("order"), order_hash.clone());
+        let state_key = (symbol_short!("state"), order_hash.clone());
+        env.storage().persistent().set(&state_key, state);
+        env.storage().persistent().extend_ttl(&order_key, ORDER_LIFETIME_THRESHOLD, ORDER_BUMP_AMOUNT);
+        env.storage().persistent().extend_ttl(&state_key, ORDER_LIFETIME_THRESHOLD, ORDER_BUMP_AMOUNT);
+    }
+
+    /// Index of the hashlock a fill of `amount` uses
+    fn fill_part(order: &Order, state: &OrderState, amount: i128) -> Result<u32, Error> {
+        let parts = order.hashlocks.len() - 1;
+        if parts == 0 && amount != order.making_amount {
+            return Err(Error::InvalidAmount);
+        }
+
+        let part = if amount == state.remaining {
+            parts
+        } else {
+            let filled = order.making_amount - state.remaining + amount;
+            ((filled - 1) * parts as i128 / order.making_amount) as u32
+        };
+
+        // A fill too small to reach a new part would reuse the previous fill's hashlock
+        if part < state.next_part {
+            return Err(Error::InvalidAmount);
+        }
+        Ok(part)
+    }
+
+    fn fill_immutables(
+        order: &Order,
+        order_hash: &BytesN<32>,
+        part: u32,
+        resolver: &Address,
+        amount: i128,
+        safety_deposit: i128,
+    ) -> Immutables {
+        Immutables {
+            order_hash: order_hash.clone(),
+            hashlock: order.hashlocks.get(part).unwrap(),
+            maker: order.maker.clone(),
+            taker: resolver.clone(),
+            token: order.token.clone(),
+            amount,
+            safety_deposit,
+            src_chain_id: order.src_chain_id,
+            dst_chain_id: order.dst_chain_id,
+            time_base: order.time_base,
+            deployed_at: 0,
+            src_withdrawal_start: order.src_withdrawal_start,
+            src_public_withdrawal_start: order.src_public_withdrawal_start,
+            src_cancellation_start: order.src_cancellation_start,
+            src_public_cancellation_start: order.src_public_cancellation_start,
+            dst_withdrawal_start: order.dst_withdrawal_start,
+            dst_public_withdrawal_start: order.dst_public_withdrawal_start,
+            dst_cancellation_start: order.dst_cancellation_start,
+            allow_clawback: order.allow_clawback,
+            fees: order.fees.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use escrow_src::{EscrowSrcClient, State};
+    use escrow_src_factory::EscrowSrcFactory;
+    use soroban_sdk::{
+        testutils::{Address as _, Events as _, Ledger},
+        token::{StellarAssetClient, TokenClient},
+        vec, Address, Bytes, Env, IntoVal, Val,
+    };
+
+    /// EscrowSrc wasm the factory deploys (build it with `make build` first)
+    mod escrow_wasm {
+        soroban_sdk::contractimport!(file = "../../target/wasm32v1-none/release/escrow_src.wasm");
+    }
+
+    struct Setup<'a> {
+        env: Env,
+        client: OrderProtocolClient<'a>,
+        contract_id: Address,
+        factory: EscrowSrcFactoryClient<'a>,
+        native_token: Address,
+        token: Address,
+        maker: Address,
+        resolver: Address,
+    }
+
+    /// Protocol wired to a factory, with a whitelisted resolver holding 1000 XLM
+    /// and a maker holding and approving 1000 tokens
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let admin = Address::generate(&env);
+        let wasm_hash = env.deployer().upload_contract_wasm(escrow_wasm::WASM);
+        let native_token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+        let factory_id = env.register(EscrowSrcFactory, (admin.clone(), wasm_hash, native_token.clone(), 1000u64));
+        let contract_id = env.register(OrderProtocol, (admin, factory_id.clone(), native_token.clone()));
+        let client = OrderProtocolClient::new(&env, &contract_id);
+        escrow_src_factory::EscrowSrcFactoryClient::new(&env, &factory_id).set_order_protocol(&contract_id);
+
+        let resolver = Address::generate(&env);
+        client.set_resolver(&resolver, &true);
+        StellarAssetClient::new(&env, &native_token).mint(&resolver, &1000);
+
+        let token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+        let maker = Address::generate(&env);
+        StellarAssetClient::new(&env, &token).mint(&maker, &1000);
+        TokenClient::new(&env, &token).approve(&maker, &contract_id, &1000, &1000);
+
+        let factory = EscrowSrcFactoryClient::new(&env, &factory_id);
+        Setup { env, client, contract_id, factory, native_token, token, maker, resolver }
+    }
+
+    fn hashlock(env: &Env, n: u8) -> BytesN<32> {
+        env.crypto().sha256(&Bytes::from_slice(env, &[n; 32])).into()
+    }
+
+    /// Order for 1000 tokens split into `parts` parts (filled at once for 0)
+    fn order(s: &Setup, parts: u8) -> Order {
+        let mut hashlocks = Vec::new(&s.env);
+        for n in 0..=parts {
+            hashlocks.push_back(hashlock(&s.env, n));
+        }
+        Order {
+            salt: BytesN::from_array(&s.env, &[parts; 32]),
+            maker: s.maker.clone(),
+            token: s.token.clone(),
+            making_amount: 1000,
+            expiration: 500,
+            src_chain_id: 1000,
+            dst_chain_id: 1,
+            hashlocks,
+            time_base: TimeBase::Timestamp,
+            src_withdrawal_start: 60,
+            src_public_withdrawal_start: 120,
+            src_cancellation_start: 300,
+            src_public_cancellation_start: 600,
+            dst_withdrawal_start: 60,
+            dst_public_withdrawal_start: 120,
+            dst_cancellation_start: 300,
+            allow_clawback: false,
+            fees: Vec::new(&s.env),
+        }
+    }
+
+    /// Address of the escrow a fill using hashlock `part` will be created at
+    fn prepare_escrow(s: &Setup, order: &Order, part: u32, amount: i128, safety_deposit: i128) -> Address {
+        let order_hash = s.client.hash_order(order);
+        let immutables = OrderProtocol::fill_immutables(order, &order_hash, part, &s.resolver, amount, safety_deposit);
+        s.factory.compute_escrow_address(&immutables)
+    }
+
+    fn balance(s: &Setup, token: &Address, id: &Address) -> i128 {
+        TokenClient::new(&s.env, token).balance(id)
+    }
+
+    /// Topics and data of the events the protocol published in the last invocation
+    fn protocol_events(s: &Setup) -> Vec<(Vec<Val>, Val)> {
+        let mut events = Vec::new(&s.env);
+        for (contract, topics, data) in s.env.events().all().iter() {
+            if contract == s.contract_id {
+                events.push_back((topics, data));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_fill_order() {
+        let s = setup();
+        let order = order(&s, 0);
+        let order_hash = s.client.place_order(&order);
+        assert_eq!(
+            protocol_events(&s),
+            vec![
+                &s.env,
+                (
+                    (symbol_short!("placed"), order_hash.clone(), s.maker.clone()).into_val(&s.env),
+                    1000i128.into_val(&s.env)
+                )
+            ]
+        );
+        assert_eq!(s.client.get_order_state(&order_hash), Some(OrderState { remaining: 1000, next_part: 0 }));
+        let escrow_address = prepare_escrow(&s, &order, 0, 1000, 100);
+
+        assert_eq!(s.client.fill_order(&s.resolver, &order_hash, &1000, &100), escrow_address);
+        assert_eq!(
+            protocol_events(&s),
+            vec![
+                &s.env,
+                (
+                    (symbol_short!("filled"), order_hash.clone(), s.resolver.clone()).into_val(&s.env),
+                    (escrow_address.clone(), 1000i128, 0i128).into_val(&s.env)
+                )
+            ]
+        );
+
+        // The maker's tokens and the safety deposit went straight into the escrow
+        assert_eq!(balance(&s, &s.token, &escrow_address), 1000);
+        assert_eq!(balance(&s, &s.token, &s.maker), 0);
+        assert_eq!(balance(&s, &s.native_token, &escrow_address), 100);
+        assert_eq!(balance(&s, &s.native_token, &s.resolver), 900);
+
+        let escrow = EscrowSrcClient::new(&s.env, &escrow_address);
+        assert_eq!(escrow.get_state(), State::Active);
+        let immutables = escrow.get_immutables();
+        assert_eq!(immutables.order_hash, order_hash);
+        assert_eq!(immutables.hashlock, hashlock(&s.env, 0));
+        assert_eq!(immutables.taker, s.resolver);
+
+        assert_eq!(s.client.get_order_state(&order_hash), Some(OrderState { remaining: 0, next_part: 1 }));
+        assert_eq!(s.client.try_fill_order(&s.resolver, &order_hash, &1, &0), Err(Ok(Error::InvalidAmount)));
+    }
+
+    #[test]
+    fn test_partial_fills() {
+        let s = setup();
+        let order = order(&s, 4);
+        let order_hash = s.client.place_order(&order);
+
+        // Orders filled at once cannot be split, but this one can
+        let whole = s.client.place_order(&self::order(&s, 0));
+        assert_eq!(s.client.try_fill_order(&s.resolver, &whole, &400, &0), Err(Ok(Error::InvalidAmount)));
+
+        // 400 of 1000 reaches the second quarter
+        let first = prepare_escrow(&s, &order, 1, 400, 0);
+        assert_eq!(s.client.fill_order(&s.resolver, &order_hash, &400, &0), first);
+        assert_eq!(s.client.get_order_state(&order_hash), Some(OrderState { remaining: 600, next_part: 2 }));
+
+        // Another 50 stays in the second quarter, whose hashlock is used
+        assert_eq!(s.client.try_fill_order(&s.resolver, &order_hash, &50, &0), Err(Ok(Error::InvalidAmount)));
+
+        // 200 more reaches the third quarter
+        let second = prepare_escrow(&s, &order, 2, 200, 0);
+        assert_eq!(s.client.fill_order(&s.resolver, &order_hash, &200, &0), second);
+
+        // The rest completes the order with the last hashlock
+        let last = prepare_escrow(&s, &order, 4, 400, 0);
+        assert_eq!(s.client.fill_order(&s.resolver, &order_hash, &400, &0), last);
+        assert_eq!(EscrowSrcClient::new(&s.env, &last).get_immutables().hashlock, hashlock(&s.env, 4));
+
+        assert_eq!(balance(&s, &s.token, &first), 400);
+        assert_eq!(balance(&s, &s.token, &second), 200);
+        assert_eq!(balance(&s, &s.token, &last), 400);
+        assert_eq!(balance(&s, &s.token, &s.maker), 0);
+    }
+
+    #[test]
+    fn test_fill_restrictions() {
+        let s = setup();
+        let order = order(&s, 0);
+        let order_hash = s.client.place_order(&order);
+        prepare_escrow(&s, &order, 0, 1000, 100);
+
+        // Resolvers must be whitelisted
+        let stranger = Address::generate(&s.env);
+        assert_eq!(s.client.try_fill_order(&stranger, &order_hash, &1000, &100), Err(Ok(Error::AuthNotAllowed)));
+        s.client.set_resolver(&s.resolver, &false);
+        assert!(!s.client.is_resolver(&s.resolver));
+        assert_eq!(s.client.try_fill_order(&s.resolver, &order_hash, &1000, &100), Err(Ok(Error::AuthNotAllowed)));
+        s.client.set_resolver(&s.resolver, &true);
+
+        let unknown = BytesN::from_array(&s.env, &[9u8; 32]);
+        assert_eq!(s.client.try_fill_order(&s.resolver, &unknown, &1000, &100), Err(Ok(Error::OrderNotFound)));
+
+        // Placing the same order twice
+        assert_eq!(s.client.try_place_order(&order), Err(Ok(Error::AlreadyInitialized)));
+
+        // Expired orders
+        s.env.ledger().with_mut(|li| li.timestamp = 501);
+        assert_eq!(s.client.try_fill_order(&s.resolver, &order_hash, &1000, &100), Err(Ok(Error::WindowClosed)));
+        s.env.ledger().with_mut(|li| li.timestamp = 0);
+
+        // Cancelled orders
+        s.client.cancel_order(&order_hash);
+        assert_eq!(
+            protocol_events(&s),
+            vec![&s.env, ((symbol_short!("cancel"), order_hash.clone()).into_val(&s.env), 1000i128.into_val(&s.env))]
+        );
+        assert_eq!(s.client.try_fill_order(&s.resolver, &order_hash, &1000, &100), Err(Ok(Error::InvalidAmount)));
+        assert_eq!(balance(&s, &s.token, &s.maker), 1000);
+    }
+
+    #[test]
+    fn test_fill_without_allowance() {
+        let s = setup();
+        TokenClient::new(&s.env, &s.token).approve(&s.maker, &s.contract_id, &0, &1000);
+        let order = order(&s, 0);
+        let order_hash = s.client.place_order(&order);
+        prepare_escrow(&s, &order, 0, 1000, 100);
+
+        assert_eq!(s.client.try_fill_order(&s.resolver, &order_hash, &1000, &100), Err(Ok(Error::TransferFailed)));
+
+        // The failed fill left the order untouched
+        assert_eq!(s.client.get_order_state(&order_hash), Some(OrderState { remaining: 1000, next_part: 0 }));
+        assert_eq!(balance(&s, &s.native_token, &s.resolver), 1000);
+    }
+
+    #[test]
+    fn test_place_order_requires_maker_auth() {
+        let s = setup();
+        s.env.set_auths(&[]);
+        assert!(s.client.try_place_order(&order(&s, 0)).is_err());
+    }
+}
@@ -0,0 +1,538 @@
+#![no_std]
+use soroban_sdk::{
+    auth::{Context, ContractContext, CustomAccountInterface},
+    contract, contractimpl, contracttype,
+    crypto::Hash, xdr::ToXdr, Address, Bytes, BytesN, Env, Symbol, TryFromVal, Vec, symbol_short
+};
+
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+/// Maximum size of the WebAuthn client data accepted in a signature
+const MAX_CLIENT_DATA_LEN: usize = 1024;
+
+/// Length of the base64url (unpadded) encoding of a 32-byte challenge
+const CHALLENGE_LEN: usize = 43;
+
+/// Index of the flags byte in the authenticator data, after the 32-byte RP ID hash
+const FLAGS_INDEX: u32 = 32;
+
+/// User Present flag of the authenticator data
+const FLAG_USER_PRESENT: u8 = 0x01;
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Size of a fee leg, either fixed or in basis points of the escrowed amount (same as EscrowSrc)
+#[contracttype]
+#[derive(Clone)]
+pub enum FeeAmount {
+    Fixed(i128),
+    Bps(u32),
+}
+
+/// Part of the withdrawn tokens paid to a protocol or integrator (same as EscrowSrc)
+#[contracttype]
+#[derive(Clone)]
+pub struct FeeLeg {
+    pub recipient: Address,
+    pub amount: FeeAmount,
+}
+
+/// Clock the timelocks of an escrow count in (same as EscrowSrc)
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeBase {
+    Timestamp,                          // Seconds of ledger close time
+    Sequence,                           // Ledger sequence numbers
+}
+
+/// Immutable parameters for the escrow (same as EscrowSrc)
+#[contracttype]
+#[derive(Clone)]
+pub struct Immutables {
+    pub order_hash: BytesN<32>,
+    pub hashlock: BytesN<32>,
+    pub maker: Address,
+    pub taker: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub safety_deposit: i128,
+    pub src_chain_id: u64,              // Chain the maker's tokens are locked on
+    pub dst_chain_id: u64,              // Chain the taker's tokens are paid out on
+    pub time_base: TimeBase,            // Clock the timelocks and deployed_at count in
+    pub deployed_at: u64,
+    // Timelock durations from deployment, in seconds or ledgers per time_base
+    pub src_withdrawal_start: u32,      // When taker can withdraw
+    pub src_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub src_cancellation_start: u32,     // When taker can cancel
+    pub src_public_cancellation_start: u32, // When anyone can cancel
+    pub dst_withdrawal_start: u32,      // When taker can withdraw
+    pub dst_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub dst_cancellation_start: u32,     // When taker can cancel
+    pub allow_clawback: bool,           // Order accepts tokens the issuer can claw back
+    pub fees: Vec<FeeLeg>,              // Paid out of the amount on withdrawal, at most MAX_FEE_LEGS
+}
+
+/// WebAuthn assertion produced by the passkey for an authorization payload
+#[contracttype]
+#[derive(Clone)]
+pub struct Signature {
+    pub authenticator_data: Bytes,
+    pub client_data_json: Bytes,        // Must carry the payload as its base64url `challenge`
+    pub signature: BytesN<64>,          // Raw r ‖ s, with s normalized to the lower half of the order
+}
+
+/// Maker account controlled by a secp256r1 passkey
+///
+/// The passkey can only authorize source escrow creation through the configured factory,
+/// for orders no larger than the limit set for their token, and the matching transfer of
+/// the order amount into the escrow. Changing the limits needs the passkey as well.
+#[contract]
+pub struct PasskeyAccount;
+
+#[contractimpl]
+impl PasskeyAccount {
+    /// Set up the account with the passkey's uncompressed SEC-1 public key and the source factory
+    pub fn __constructor(env: Env, public_key: BytesN<65>, src_factory: Address) {
+        env.storage().instance().set(&symbol_short!("pubkey"), &public_key);
+        env.storage().instance().set(&symbol_short!("factory"), &src_factory);
+    }
+
+    /// Set the largest order amount the passkey may authorize for a token (0 disallows the token)
+    pub fn set_order_limit(env: Env, token: Address, max_amount: i128) -> Result<(), Error> {
+        env.current_contract_address().require_auth();
+
+        if max_amount < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        env.storage().instance().set(&(symbol_short!("limit"), token.clone()), &max_amount);
+
+        env.events().publish((symbol_short!("limit"), token), max_amount);
+        Ok(())
+    }
+
+    /// Get the largest order amount the passkey may authorize for a token
+    pub fn get_order_limit(env: Env, token: Address) -> i128 {
+        env.storage().instance().get(&(symbol_short!("limit"), token)).unwrap_or(0)
+    }
+
+    /// Get the passkey's public key
+    pub fn get_public_key(env: Env) -> BytesN<65> {
+        env.storage().instance().get(&symbol_short!("pubkey")).unwrap()
+    }
+
+    /// Get the source factory orders are created through
+    pub fn get_src_factory(env: Env) -> Address {
+        env.storage().instance().get(&symbol_short!("factory")).unwrap()
+    }
+
+    // Helper functions
+
+    /// Check the WebAuthn assertion signs the payload (a bad signature traps in the host)
+    fn verify_assertion(env: &Env, payload: &Hash<32>, signature: &Signature) -> Result<(), Error> {
+        let flags = signature.authenticator_data.get(FLAGS_INDEX).ok_or(Error::InvalidSignature)?;
+        if flags & FLAG_USER_PRESENT == 0 {
+            return Err(Error::InvalidSignature);
+        }
+
+        let len = signature.client_data_json.len() as usize;
+        if len > MAX_CLIENT_DATA_LEN {
+            return Err(Error::InvalidSignature);
+        }
+        let mut client_data = [0u8; MAX_CLIENT_DATA_LEN];
+        signature.client_data_json.copy_into_slice(&mut client_data[..len]);
+
+        let mut challenge = [0u8; 13 + CHALLENGE_LEN + 1];
+        challenge[..13].copy_from_slice(b"\"challenge\":\"");
+        base64url_encode(&payload.to_array(), &mut challenge[13..13 + CHALLENGE_LEN]);
+        challenge[13 + CHALLENGE_LEN] = b'"';
+
+        if !contains(&client_data[..len], b"\"type\":\"webauthn.get\"") || !contains(&client_data[..len], &challenge) {
+            return Err(Error::InvalidSignature);
+        }
+
+        // WebAuthn signs sha256(authenticatorData ‖ sha256(clientDataJSON))
+        let mut message = signature.authenticator_data.clone();
+        message.append(&env.crypto().sha256(&signature.client_data_json).into());
+        let digest = env.crypto().sha256(&message);
+
+        let public_key = Self::get_public_key(env.clone());
+        env.crypto().secp256r1_verify(&public_key, &digest, &signature.signature);
+        Ok(())
+    }
+
+    /// Check a `createsrc` authorization against the order limits, returning the transfer it allows
+    fn allowed_order(env: &Env, context: &ContractContext) -> Result<(Address, Address, i128), Error> {
+        let immutables = context.args.get(0)
+            .and_then(|arg| Immutables::try_from_val(env, &arg).ok())
+            .ok_or(Error::InvalidImmutables)?;
+
+        if immutables.maker != env.current_contract_address() {
+            return Err(Error::AuthNotAllowed);
+        }
+        if immutables.amount <= 0
+            || immutables.amount > Self::get_order_limit(env.clone(), immutables.token.clone())
+        {
+            return Err(Error::OrderLimitExceeded);
+        }
+
+        Ok((immutables.token.clone(), Self::escrow_address(env, &context.contract, &immutables), immutables.amount))
+    }
+
+    /// Address of the source escrow the factory deploys for an order (same as EscrowSrcFactory)
+    ///
+    /// Computed here rather than asked of the factory, which is mid-call while it checks our auth.
+    fn escrow_address(env: &Env, factory: &Address, immutables: &Immutables) -> Address {
+        let mut immutables = immutables.clone();
+        immutables.deployed_at = 0;
+        let mut data = Bytes::from_array(env, &env.ledger().network_id().to_array());
+        data.append(&immutables.to_xdr(env));
+        let salt: BytesN<32> = env.crypto().sha256(&data).into();
+
+        env.deployer().with_address(factory.clone(), salt).deployed_address()
+    }
+
+    /// Decode a `transfer(from, to, amount)` call as `(token, to, amount)`, if it moves our funds
+    fn transfer_of(env: &Env, context: &ContractContext) -> Option<(Address, Address, i128)> {
+        if context.fn_name != Symbol::new(env, "transfer") || context.args.len() != 3 {
+            return None;
+        }
+        let from = Address::try_from_val(env, &context.args.get(0)?).ok()?;
+        let to = Address::try_from_val(env, &context.args.get(1)?).ok()?;
+        let amount = i128::try_from_val(env, &context.args.get(2)?).ok()?;
+
+        if from != env.current_contract_address() {
+            return None;
+        }
+        Some((context.contract.clone(), to, amount))
+    }
+}
+
+#[contractimpl]
+impl CustomAccountInterface for PasskeyAccount {
+    type Signature = Signature;
+    type Error = Error;
+
+    /// Verify the passkey signed the payload and every authorized call fits the spending policy
+    #[allow(non_snake_case)]
+    fn __check_auth(
+        env: Env,
+        signature_payload: Hash<32>,
+        signature: Signature,
+        auth_contexts: Vec<Context>,
+    ) -> Result<(), Error> {
+        Self::verify_assertion(&env, &signature_payload, &signature)?;
+
+        let this = env.current_contract_address();
+        let factory = Self::get_src_factory(env.clone());
+
+        // Orders first, so each transfer can be matched to the order it funds
+        let mut allowed_transfers = Vec::new(&env);
+        for context in auth_contexts.iter() {
+            if let Context::Contract(c) = &context {
+                if c.contract == factory && c.fn_name == Symbol::new(&env, "createsrc") {
+                    allowed_transfers.push_back(Self::allowed_order(&env, c)?);
+                }
+            }
+        }
+
+        for context in auth_contexts.iter() {
+            match &context {
+                // Managing the account itself
+                Context::Contract(c) if c.contract == this => {}
+                Context::Contract(c) if c.contract == factory && c.fn_name == Symbol::new(&env, "createsrc") => {}
+                // Funding an authorized order's escrow, each at most once
+                Context::Contract(c) => {
+                    let transfer = Self::transfer_of(&env, c).ok_or(Error::AuthNotAllowed)?;
+                    let index = allowed_transfers.first_index_of(&transfer).ok_or(Error::AuthNotAllowed)?;
+                    allowed_transfers.remove(index);
+                }
+                _ => return Err(Error::AuthNotAllowed),
+            }
+        }
+
+        env.events().publish((symbol_short!("authorize"),), auth_contexts.len());
+        Ok(())
+    }
+}
+
+/// Encode 32 bytes as unpadded base64url into `out`
+fn base64url_encode(input: &[u8; 32], out: &mut [u8]) {
+    let mut pos = 0;
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..chunk.len() + 1 {
+            out[pos] = BASE64URL[((n >> (18 - 6 * i)) & 0x3f) as usize];
+            pos += 1;
+        }
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p256::ecdsa::{signature::hazmat::PrehashSigner, Signature as P256Signature, SigningKey};
+    use soroban_sdk::{
+        testutils::{Address as _, BytesN as _, Events as _},
+        vec, Env, IntoVal, InvokeError, Val,
+    };
+
+    struct Setup {
+        env: Env,
+        account: Address,
+        factory: Address,
+        token: Address,
+        key: SigningKey,
+    }
+
+    fn setup() -> Setup {
+        let env = Env::default();
+        let key = SigningKey::from_bytes(&[7u8; 32].into()).unwrap();
+        let point = key.verifying_key().to_encoded_point(false);
+        let public_key = BytesN::<65>::from_array(&env, point.as_bytes().try_into().unwrap());
+
+        let factory = Address::generate(&env);
+        let token = Address::generate(&env);
+        let account = env.register(PasskeyAccount, (public_key, factory.clone()));
+
+        env.mock_all_auths();
+        PasskeyAccountClient::new(&env, &account).set_order_limit(&token, &1000);
+        env.set_auths(&[]);
+
+        Setup { env, account, factory, token, key }
+    }
+
+    /// Passkey assertion over a payload, as a browser would produce it
+    fn assertion(s: &Setup, payload: &BytesN<32>) -> Signature {
+        let mut challenge = [0u8; CHALLENGE_LEN];
+        base64url_encode(&payload.to_array(), &mut challenge);
+        let challenge = core::str::from_utf8(&challenge).unwrap();
+
+        let mut json = [0u8; 256];
+        let parts: [&[u8]; 3] = [
+            b"{\"type\":\"webauthn.get\",\"challenge\":\"",
+            challenge.as_bytes(),
+            b"\",\"origin\":\"https://app.example\",\"crossOrigin\":false}",
+        ];
+        let mut len = 0;
+        for part in parts {
+            json[len..len + part.len()].copy_from_slice(part);
+            len += part.len();
+        }
+        let client_data_json = Bytes::from_slice(&s.env, &json[..len]);
+
+        // RP ID hash, flags (user present and verified), sign count
+        let mut authenticator_data = Bytes::from_slice(&s.env, &[0x49u8; 32]);
+        authenticator_data.extend_from_array(&[0x05, 0, 0, 0, 1]);
+
+        let mut message = authenticator_data.clone();
+        message.append(&s.env.crypto().sha256(&client_data_json).into());
+        let digest = s.env.crypto().sha256(&message).to_array();
+
+        let signature: P256Signature = s.key.sign_prehash(&digest).unwrap();
+        let signature = signature.normalize_s().unwrap_or(signature);
+
+        Signature {
+            authenticator_data,
+            client_data_json,
+            signature: BytesN::from_array(&s.env, &signature.to_bytes().into()),
+        }
+    }
+
+    fn immutables(s: &Setup, amount: i128) -> Immutables {
+        Immutables {
+            order_hash: BytesN::from_array(&s.env, &[1u8; 32]),
+            hashlock: BytesN::from_array(&s.env, &[2u8; 32]),
+            maker: s.account.clone(),
+            taker: Address::generate(&s.env),
+            token: s.token.clone(),
+            amount,
+            safety_deposit: 10,
+            src_chain_id: 1000,
+            dst_chain_id: 1,
+            time_base: TimeBase::Timestamp,
+            deployed_at: 0,
+            src_withdrawal_start: 60,
+            src_public_withdrawal_start: 120,
+            src_cancellation_start: 300,
+            src_public_cancellation_start: 600,
+            dst_withdrawal_start: 60,
+            dst_public_withdrawal_start: 120,
+            dst_cancellation_start: 300,
+            allow_clawback: false,
+            fees: Vec::new(&s.env),
+        }
+    }
+
+    fn createsrc(s: &Setup, immutables: &Immutables) -> Context {
+        Context::Contract(ContractContext {
+            contract: s.factory.clone(),
+            fn_name: Symbol::new(&s.env, "createsrc"),
+            args: vec![&s.env, immutables.into_val(&s.env)],
+        })
+    }
+
+    fn transfer(s: &Setup, to: &Address, amount: i128) -> Context {
+        Context::Contract(ContractContext {
+            contract: s.token.clone(),
+            fn_name: Symbol::new(&s.env, "transfer"),
+            args: vec![&s.env, s.account.into_val(&s.env), to.into_val(&s.env), amount.into_val(&s.env)],
+        })
+    }
+
+    fn check_auth(s: &Setup, payload: &BytesN<32>, signature: Signature, contexts: Vec<Context>) -> Result<(), Result<Error, InvokeError>> {
+        s.env.try_invoke_contract_check_auth::<Error>(&s.account, payload, signature.into_val(&s.env), &contexts)
+    }
+
+    /// Topics and data of the events the account published in the last invocation
+    fn account_events(s: &Setup) -> Vec<(Vec<Val>, Val)> {
+        let mut events = Vec::new(&s.env);
+        for (contract, topics, data) in s.env.events().all().iter() {
+            if contract == s.account {
+                events.push_back((topics, data));
+            }
+        }
+        events
+    }
+
+    fn escrow(s: &Setup, immutables: &Immutables) -> Address {
+        s.env.as_contract(&s.account, || PasskeyAccount::escrow_address(&s.env, &s.factory, immutables))
+    }
+
+    #[test]
+    fn test_base64url() {
+        let mut out = [0u8; CHALLENGE_LEN];
+        base64url_encode(&[0xfb; 32], &mut out);
+        assert_eq!(&out, b"-_v7-_v7-_v7-_v7-_v7-_v7-_v7-_v7-_v7-_v7-_s");
+    }
+
+    #[test]
+    fn test_escrow_address_matches_factory() {
+        let s = setup();
+        let native_token = Address::generate(&s.env);
+        let wasm_hash = BytesN::from_array(&s.env, &[0u8; 32]);
+        let factory = s.env.register(escrow_src_factory::EscrowSrcFactory, (s.account.clone(), wasm_hash, native_token, 1000u64));
+
+        // The order as the factory receives it, deployment time and all
+        let mut order = immutables(&s, 1000);
+        order.deployed_at = 12345;
+        let val: Val = order.clone().into_val(&s.env);
+        let factory_order = escrow_src_factory::Immutables::try_from_val(&s.env, &val).unwrap();
+        assert_eq!(
+            s.env.as_contract(&s.account, || PasskeyAccount::escrow_address(&s.env, &factory, &order)),
+            escrow_src_factory::EscrowSrcFactoryClient::new(&s.env, &factory).compute_escrow_address(&factory_order)
+        );
+    }
+
+    #[test]
+    fn test_createsrc_within_limit() {
+        let s = setup();
+        let payload = BytesN::random(&s.env);
+        let order = immutables(&s, 1000);
+
+        let contexts = vec![&s.env, createsrc(&s, &order), transfer(&s, &escrow(&s, &order), 1000)];
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &payload), contexts), Ok(()));
+        assert_eq!(
+            account_events(&s),
+            vec![&s.env, ((symbol_short!("authorize"),).into_val(&s.env), 2u32.into_val(&s.env))]
+        );
+    }
+
+    #[test]
+    fn test_createsrc_over_limit() {
+        let s = setup();
+        let payload = BytesN::random(&s.env);
+        let order = immutables(&s, 1001);
+
+        let contexts = vec![&s.env, createsrc(&s, &order), transfer(&s, &escrow(&s, &order), 1001)];
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &payload), contexts), Err(Ok(Error::OrderLimitExceeded)));
+
+        // Tokens without a limit cannot be ordered at all
+        let mut order = immutables(&s, 1);
+        order.token = Address::generate(&s.env);
+        let contexts = vec![&s.env, createsrc(&s, &order)];
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &payload), contexts), Err(Ok(Error::OrderLimitExceeded)));
+    }
+
+    #[test]
+    fn test_disallowed_calls() {
+        let s = setup();
+        let payload = BytesN::random(&s.env);
+        let order = immutables(&s, 500);
+
+        // A plain transfer out of the account
+        let contexts = vec![&s.env, transfer(&s, &Address::generate(&s.env), 500)];
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &payload), contexts), Err(Ok(Error::AuthNotAllowed)));
+
+        // An order's transfer redirected away from its escrow
+        let contexts = vec![&s.env, createsrc(&s, &order), transfer(&s, &Address::generate(&s.env), 500)];
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &payload), contexts), Err(Ok(Error::AuthNotAllowed)));
+
+        // Funding the same escrow twice
+        let to = escrow(&s, &order);
+        let contexts = vec![&s.env, createsrc(&s, &order), transfer(&s, &to, 500), transfer(&s, &to, 500)];
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &payload), contexts), Err(Ok(Error::AuthNotAllowed)));
+
+        // Other factory entry points
+        let contexts = vec![&s.env, Context::Contract(ContractContext {
+            contract: s.factory.clone(),
+            fn_name: Symbol::new(&s.env, "createsrcs"),
+            args: vec![&s.env, vec![&s.env, order.clone()].into_val(&s.env)],
+        })];
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &payload), contexts), Err(Ok(Error::AuthNotAllowed)));
+
+        // Orders made for another maker
+        let mut order = immutables(&s, 500);
+        order.maker = Address::generate(&s.env);
+        let contexts = vec![&s.env, createsrc(&s, &order)];
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &payload), contexts), Err(Ok(Error::AuthNotAllowed)));
+    }
+
+    #[test]
+    fn test_manage_account() {
+        let s = setup();
+        let payload = BytesN::random(&s.env);
+        let contexts = vec![&s.env, Context::Contract(ContractContext {
+            contract: s.account.clone(),
+            fn_name: Symbol::new(&s.env, "set_order_limit"),
+            args: vec![&s.env, s.token.into_val(&s.env), 5000i128.into_val(&s.env)],
+        })];
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &payload), contexts), Ok(()));
+
+        s.env.mock_all_auths();
+        let client = PasskeyAccountClient::new(&s.env, &s.account);
+        client.set_order_limit(&s.token, &5000);
+        assert_eq!(
+            account_events(&s),
+            vec![&s.env, ((symbol_short!("limit"), s.token.clone()).into_val(&s.env), 5000i128.into_val(&s.env))]
+        );
+        assert_eq!(client.get_order_limit(&s.token), 5000);
+    }
+
+    #[test]
+    fn test_invalid_assertion() {
+        let s = setup();
+        let payload = BytesN::random(&s.env);
+        let order = immutables(&s, 1000);
+        let contexts = vec![&s.env, createsrc(&s, &order), transfer(&s, &escrow(&s, &order), 1000)];
+
+        // Signed for another payload
+        let other = BytesN::random(&s.env);
+        assert_eq!(check_auth(&s, &payload, assertion(&s, &other), contexts.clone()), Err(Ok(Error::InvalidSignature)));
+
+        // User not present
+        let mut signature = assertion(&s, &payload);
+        signature.authenticator_data.set(FLAGS_INDEX, 0x04);
+        assert_eq!(check_auth(&s, &payload, signature, contexts.clone()), Err(Ok(Error::InvalidSignature)));
+
+        // Signature over different client data
+        let mut signature = assertion(&s, &payload);
+        signature.client_data_json.push_back(b' ');
+        assert!(check_auth(&s, &payload, signature, contexts).is_err());
+    }
+}
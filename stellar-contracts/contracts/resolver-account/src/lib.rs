@@ -0,0 +1,633 @@
+#![no_std]
+use soroban_sdk::{
+    auth::{Context, ContractContext, CustomAccountInterface},
+    contract, contractimpl, contracttype,
+    crypto::Hash, xdr::ToXdr, Address, Bytes, BytesN, Env, Symbol, TryFromVal, Vec, symbol_short
+};
+
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+/// Approximate number of ledgers closed per day
+const DAY_IN_LEDGERS: u32 = 17_280;
+
+/// Length of the window daily limits apply to
+const DAY_IN_SECONDS: u64 = 86_400;
+
+/// Spent amounts are kept this many ledgers ahead, past the end of their day...
+const SPENT_BUMP_AMOUNT: u32 = 2 * DAY_IN_LEDGERS;
+
+/// ...whenever they are touched with less than this left
+const SPENT_LIFETIME_THRESHOLD: u32 = DAY_IN_LEDGERS;
+
+/// Escrow entry points the hot key may authorize as taker or public caller
+///
+/// `wdrawto` is left out: it pays the escrowed tokens to any address.
+const ESCROW_FUNCTIONS: [&str; 7] = [
+    "withdraw",
+    "cancel",
+    "public_withdraw",
+    "public_cancel",
+    "withdraw_registered",
+    "public_withdraw_registered",
+    "claim",
+];
+
+/// Size of a fee leg, either fixed or in basis points of the escrowed amount (same as the escrow contracts)
+#[contracttype]
+#[derive(Clone)]
+pub enum FeeAmount {
+    Fixed(i128),
+    Bps(u32),
+}
+
+/// Part of the withdrawn tokens paid to a protocol or integrator (same as the escrow contracts)
+#[contracttype]
+#[derive(Clone)]
+pub struct FeeLeg {
+    pub recipient: Address,
+    pub amount: FeeAmount,
+}
+
+/// Clock the timelocks of an escrow count in (same as EscrowSrcFactory)
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeBase {
+    Timestamp,                          // Seconds of ledger close time
+    Sequence,                           // Ledger sequence numbers
+}
+
+/// Immutable parameters for a source escrow (same as EscrowSrcFactory)
+#[contracttype]
+#[derive(Clone)]
+pub struct SrcImmutables {
+    pub order_hash: BytesN<32>,
+    pub hashlock: BytesN<32>,
+    pub maker: Address,
+    pub taker: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub safety_deposit: i128,
+    pub src_chain_id: u64,              // Chain the maker's tokens are locked on
+    pub dst_chain_id: u64,              // Chain the taker's tokens are paid out on
+    pub time_base: TimeBase,            // Clock the timelocks and deployed_at count in
+    pub deployed_at: u64,
+    // Timelock durations from deployment, in seconds or ledgers per time_base
+    pub src_withdrawal_start: u32,      // When taker can withdraw
+    pub src_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub src_cancellation_start: u32,     // When taker can cancel
+    pub src_public_cancellation_start: u32, // When anyone can cancel
+    pub dst_withdrawal_start: u32,      // When taker can withdraw
+    pub dst_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub dst_cancellation_start: u32,     // When taker can cancel
+    pub allow_clawback: bool,           // Order accepts tokens the issuer can claw back
+    pub fees: Vec<FeeLeg>,              // Paid out of the amount on withdrawal, at most MAX_FEE_LEGS
+}
+
+/// Immutable parameters for a destination escrow (same as EscrowDstFactory)
+#[contracttype]
+#[derive(Clone)]
+pub struct DstImmutables {
+    pub order_hash: BytesN<32>,
+    pub hashlock: BytesN<32>,
+    pub maker: Address,
+    pub receiver: Option<Address>,      // Paid on withdrawal instead of the maker, if set
+    pub taker: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub safety_deposit: i128,
+    pub src_chain_id: u64,              // Chain the maker's tokens are locked on
+    pub dst_chain_id: u64,              // Chain the taker's tokens are paid out on
+    pub time_base: TimeBase,            // Clock the timelocks and deployed_at count in
+    pub deployed_at: u64,
+    // Timelock durations from deployment, in seconds or ledgers per time_base
+    pub src_withdrawal_start: u32,      // When taker can withdraw
+    pub src_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub src_cancellation_start: u32,     // When taker can cancel
+    pub src_public_cancellation_start: u32, // When anyone can cancel
+    pub dst_withdrawal_start: u32,      // When taker can withdraw
+    pub dst_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub dst_cancellation_start: u32,     // When taker can cancel
+    pub allow_clawback: bool,           // Order accepts tokens the issuer can claw back
+    pub fees: Vec<FeeLeg>,              // Paid out of the amount on withdrawal, at most MAX_FEE_LEGS
+    // Surplus sharing, set by the factory from the order's parameters
+    pub estimated_amount: i128,         // Taking amount the order expected
+    pub surplus_percentage: u32,        // Share of the excess over the estimate paid to the protocol, 0 to 100
+    pub protocol_fee_receiver: Option<Address>, // Receives the surplus share, if any
+}
+
+/// Immutables of an escrow a signature touches, from which its address is derived
+#[contracttype]
+#[derive(Clone)]
+pub enum EscrowImmutables {
+    Src(SrcImmutables),
+    /// As recorded by the factory, with the surplus share filled in
+    Dst(DstImmutables),
+}
+
+/// Hot key signature over the authorization payload
+///
+/// `escrows` lists the escrows called or paid by the authorized calls. They are
+/// checked against the factories' address derivation rather than asked of the
+/// factories, which may be mid-call while the account checks its auth.
+#[contracttype]
+#[derive(Clone)]
+pub struct Signature {
+    pub signature: BytesN<64>,
+    pub escrows: Vec<EscrowImmutables>,
+}
+
+/// Resolver treasury account whose ed25519 hot key can only run escrow fills
+///
+/// The hot key may call the configured factories and escrows derived from them,
+/// and transfer tokens only into the resolver's own escrows, within a daily limit
+/// per token. The admin, a separate cold account, sets the limits and rotates the key.
+#[contract]
+pub struct ResolverAccount;
+
+#[contractimpl]
+impl ResolverAccount {
+    /// Configure the account with its admin, hot key and escrow factories
+    pub fn __constructor(env: Env, admin: Address, signer: BytesN<32>, src_factory: Address, dst_factory: Address) {
+        env.storage().instance().set(&symbol_short!("admin"), &admin);
+        env.storage().instance().set(&symbol_short!("signer"), &signer);
+        env.storage().instance().set(&symbol_short!("srcfact"), &src_factory);
+        env.storage().instance().set(&symbol_short!("dstfact"), &dst_factory);
+    }
+
+    /// Replace the hot key (admin only)
+    pub fn set_signer(env: Env, signer: BytesN<32>) {
+        Self::get_admin(env.clone()).require_auth();
+        env.storage().instance().set(&symbol_short!("signer"), &signer);
+
+        env.events().publish((symbol_short!("signer"),), signer);
+    }
+
+    /// Set how much of a token the hot key may move per day (admin only, 0 disallows the token)
+    pub fn set_daily_limit(env: Env, token: Address, limit: i128) -> Result<(), Error> {
+        Self::get_admin(env.clone()).require_auth();
+
+        if limit < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        env.storage().instance().set(&(symbol_short!("limit"), token.clone()), &limit);
+
+        env.events().publish((symbol_short!("limit"), token), limit);
+        Ok(())
+    }
+
+    /// Get how much of a token the hot key may move per day
+    pub fn get_daily_limit(env: Env, token: Address) -> i128 {
+        env.storage().instance().get(&(symbol_short!("limit"), token)).unwrap_or(0)
+    }
+
+    /// Get how much of a token the hot key has moved today
+    pub fn get_spent_today(env: Env, token: Address) -> i128 {
+        env.storage().temporary().get(&Self::spent_key(&env, &token)).unwrap_or(0)
+    }
+
+    /// Get the admin
+    pub fn get_admin(env: Env) -> Address {
+        env.storage().instance().get(&symbol_short!("admin")).unwrap()
+    }
+
+    /// Get the hot key
+    pub fn get_signer(env: Env) -> BytesN<32> {
+        env.storage().instance().get(&symbol_short!("signer")).unwrap()
+    }
+
+    // Helper functions
+
+    fn src_factory(env: &Env) -> Address {
+        env.storage().instance().get(&symbol_short!("srcfact")).unwrap()
+    }
+
+    fn dst_factory(env: &Env) -> Address {
+        env.storage().instance().get(&symbol_short!("dstfact")).unwrap()
+    }
+
+    fn spent_key(env: &Env, token: &Address) -> (Symbol, Address, u64) {
+        (symbol_short!("spent"), token.clone(), env.ledger().timestamp() / DAY_IN_SECONDS)
+    }
+
+    /// Address and taker of an escrow, derived as its factory does
+    fn escrow_of(env: &Env, immutables: &EscrowImmutables) -> (Address, Address) {
+        match immutables {
+            EscrowImmutables::Src(immutables) => {
+                // Same salt as EscrowSrcFactory
+                let mut immutables = immutables.clone();
+                immutables.deployed_at = 0;
+                let mut data = Bytes::from_array(env, &env.ledger().network_id().to_array());
+                data.append(&immutables.clone().to_xdr(env));
+                let salt: BytesN<32> = env.crypto().sha256(&data).into();
+
+                let escrow = env.deployer().with_address(Self::src_factory(env), salt).deployed_address();
+                (escrow, immutables.taker.clone())
+            }
+            EscrowImmutables::Dst(immutables) => {
+                // Same salt as EscrowDstFactory
+                let mut immutables = immutables.clone();
+                immutables.deployed_at = 0;
+                let mut data = Bytes::from_array(env, &env.ledger().network_id().to_array());
+                data.append(&immutables.clone().to_xdr(env));
+                let salt: BytesN<32> = env.crypto().sha256(&data).into();
+
+                let escrow = env.deployer().with_address(Self::dst_factory(env), salt).deployed_address();
+                (escrow, immutables.taker.clone())
+            }
+        }
+    }
+
+    /// Decode a `transfer(from, to, amount)` call as `(token, to, amount)`, if it moves our funds
+    fn transfer_of(env: &Env, context: &ContractContext) -> Option<(Address, Address, i128)> {
+        if context.fn_name != Symbol::new(env, "transfer") || context.args.len() != 3 {
+            return None;
+        }
+        let from = Address::try_from_val(env, &context.args.get(0)?).ok()?;
+        let to = Address::try_from_val(env, &context.args.get(1)?).ok()?;
+        let amount = i128::try_from_val(env, &context.args.get(2)?).ok()?;
+
+        if from != env.current_contract_address() {
+            return None;
+        }
+        Some((context.contract.clone(), to, amount))
+    }
+
+    /// Count a transfer against its token's limit for the day
+    fn spend(env: &Env, token: &Address, amount: i128) -> Result<(), Error> {
+        if amount < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        let key = Self::spent_key(env, token);
+        let spent = Self::get_spent_today(env.clone(), token.clone())
+            .checked_add(amount)
+            .ok_or(Error::DailyLimitExceeded)?;
+        if spent > Self::get_daily_limit(env.clone(), token.clone()) {
+            return Err(Error::DailyLimitExceeded);
+        }
+
+        env.storage().temporary().set(&key, &spent);
+        env.storage().temporary().extend_ttl(&key, SPENT_LIFETIME_THRESHOLD, SPENT_BUMP_AMOUNT);
+        Ok(())
+    }
+}
+
+#[contractimpl]
+impl CustomAccountInterface for ResolverAccount {
+    type Signature = Signature;
+    type Error = Error;
+
+    /// Verify the hot key signed the payload and every authorized call fits the policy
+    #[allow(non_snake_case)]
+    fn __check_auth(
+        env: Env,
+        signature_payload: Hash<32>,
+        signature: Signature,
+        auth_contexts: Vec<Context>,
+    ) -> Result<(), Error> {
+        // A bad signature traps in the host
+        env.crypto().ed25519_verify(
+            &Self::get_signer(env.clone()),
+            &signature_payload.to_bytes().into(),
+            &signature.signature,
+        );
+
+        let this = env.current_contract_address();
+        let factories = [Self::src_factory(&env), Self::dst_factory(&env)];
+
+        let mut escrows = Vec::new(&env);
+        let mut own_escrows = Vec::new(&env);
+        for immutables in signature.escrows.iter() {
+            let (escrow, taker) = Self::escrow_of(&env, &immutables);
+            if taker == this {
+                own_escrows.push_back(escrow.clone());
+            }
+            escrows.push_back(escrow);
+        }
+
+        for context in auth_contexts.iter() {
+            let c = match &context {
+                Context::Contract(c) => c,
+                _ => return Err(Error::AuthNotAllowed),
+            };
+
+            if factories.contains(&c.contract) {
+                continue;
+            }
+            if escrows.contains(&c.contract) {
+                if !ESCROW_FUNCTIONS.iter().any(|name| c.fn_name == Symbol::new(&env, name)) {
+                    return Err(Error::AuthNotAllowed);
+                }
+                continue;
+            }
+
+            // Anything else must be a token transfer into one of the resolver's escrows
+            let (token, to, amount) = Self::transfer_of(&env, c).ok_or(Error::AuthNotAllowed)?;
+            if !own_escrows.contains(&to) {
+                return Err(Error::AuthNotAllowed);
+            }
+            Self::spend(&env, &token, amount)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use escrow_dst_factory::{EscrowDstFactory, EscrowDstFactoryClient};
+    use escrow_src_factory::{EscrowSrcFactory, EscrowSrcFactoryClient};
+    use soroban_sdk::{
+        testutils::{Address as _, BytesN as _, Events as _, Ledger},
+        vec, Env, IntoVal, InvokeError, Val,
+    };
+
+    struct Setup {
+        env: Env,
+        account: Address,
+        client: ResolverAccountClient<'static>,
+        src_factory: Address,
+        dst_factory: Address,
+        token: Address,
+        key: SigningKey,
+    }
+
+    fn setup() -> Setup {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let admin = Address::generate(&env);
+        let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+        let native_token = Address::generate(&env);
+        let src_factory = env.register(EscrowSrcFactory, (admin.clone(), wasm_hash.clone(), native_token.clone(), 1000u64));
+        let dst_factory = env.register(EscrowDstFactory, (admin.clone(), wasm_hash, native_token, 1000u64));
+        EscrowDstFactoryClient::new(&env, &dst_factory).set_protocol_fee_receiver(&Address::generate(&env));
+
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let signer = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+        let account = env.register(ResolverAccount, (admin, signer, src_factory.clone(), dst_factory.clone()));
+        let client = ResolverAccountClient::new(&env, &account);
+
+        let token = Address::generate(&env);
+        client.set_daily_limit(&token, &1500);
+
+        Setup { env, account, client, src_factory, dst_factory, token, key }
+    }
+
+    fn sign(s: &Setup, key: &SigningKey, payload: &BytesN<32>, escrows: Vec<EscrowImmutables>) -> Signature {
+        Signature {
+            signature: BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes()),
+            escrows,
+        }
+    }
+
+    fn check_auth(s: &Setup, signature: Signature, contexts: Vec<Context>) -> Result<(), Result<Error, InvokeError>> {
+        let payload = BytesN::random(&s.env);
+        let signature = Signature { signature: sign(s, &s.key, &payload, vec![&s.env]).signature, ..signature };
+        s.env.try_invoke_contract_check_auth::<Error>(&s.account, &payload, signature.into_val(&s.env), &contexts)
+    }
+
+    /// Topics and data of the events the account published in the last invocation
+    fn account_events(s: &Setup) -> Vec<(Vec<Val>, Val)> {
+        let mut events = Vec::new(&s.env);
+        for (contract, topics, data) in s.env.events().all().iter() {
+            if contract == s.account {
+                events.push_back((topics, data));
+            }
+        }
+        events
+    }
+
+    fn proofs(s: &Setup, escrows: &[EscrowImmutables]) -> Signature {
+        let mut list = Vec::new(&s.env);
+        for escrow in escrows {
+            list.push_back(escrow.clone());
+        }
+        Signature { signature: BytesN::from_array(&s.env, &[0u8; 64]), escrows: list }
+    }
+
+    fn src_immutables(s: &Setup, taker: &Address) -> SrcImmutables {
+        SrcImmutables {
+            order_hash: BytesN::from_array(&s.env, &[1u8; 32]),
+            hashlock: BytesN::from_array(&s.env, &[2u8; 32]),
+            maker: Address::generate(&s.env),
+            taker: taker.clone(),
+            token: Address::generate(&s.env),
+            amount: 1000,
+            safety_deposit: 100,
+            src_chain_id: 1000,
+            dst_chain_id: 1,
+            time_base: TimeBase::Timestamp,
+            deployed_at: 0,
+            src_withdrawal_start: 60,
+            src_public_withdrawal_start: 120,
+            src_cancellation_start: 300,
+            src_public_cancellation_start: 600,
+            dst_withdrawal_start: 60,
+            dst_public_withdrawal_start: 120,
+            dst_cancellation_start: 300,
+            allow_clawback: false,
+            fees: Vec::new(&s.env),
+        }
+    }
+
+    /// Destination immutables as the factory records them for a 1000 token fill
+    fn dst_immutables(s: &Setup, taker: &Address, n: u8) -> DstImmutables {
+        DstImmutables {
+            order_hash: BytesN::from_array(&s.env, &[n; 32]),
+            hashlock: BytesN::from_array(&s.env, &[2u8; 32]),
+            maker: Address::generate(&s.env),
+            receiver: None,
+            taker: taker.clone(),
+            token: s.token.clone(),
+            amount: 1000,
+            safety_deposit: 100,
+            src_chain_id: 1,
+            dst_chain_id: 1000,
+            time_base: TimeBase::Timestamp,
+            deployed_at: 0,
+            src_withdrawal_start: 60,
+            src_public_withdrawal_start: 120,
+            src_cancellation_start: 300,
+            src_public_cancellation_start: 600,
+            dst_withdrawal_start: 60,
+            dst_public_withdrawal_start: 120,
+            dst_cancellation_start: 300,
+            allow_clawback: false,
+            fees: vec![&s.env, FeeLeg { recipient: Address::generate(&s.env), amount: FeeAmount::Bps(10) }],
+            estimated_amount: 950,
+            surplus_percentage: 50,
+            protocol_fee_receiver: EscrowDstFactoryClient::new(&s.env, &s.dst_factory).get_protocol_fee_receiver(),
+        }
+    }
+
+    fn escrow(s: &Setup, immutables: &EscrowImmutables) -> Address {
+        s.env.as_contract(&s.account, || ResolverAccount::escrow_of(&s.env, immutables).0)
+    }
+
+    fn call(s: &Setup, contract: &Address, fn_name: &str, args: Vec<Val>) -> Context {
+        Context::Contract(ContractContext { contract: contract.clone(), fn_name: Symbol::new(&s.env, fn_name), args })
+    }
+
+    fn transfer(s: &Setup, token: &Address, to: &Address, amount: i128) -> Context {
+        call(s, token, "transfer", vec![&s.env, s.account.into_val(&s.env), to.into_val(&s.env), amount.into_val(&s.env)])
+    }
+
+    /// Contexts of a destination fill: the factory call and the token pull into the escrow
+    fn dst_fill(s: &Setup, immutables: &DstImmutables) -> Vec<Context> {
+        let to = escrow(s, &EscrowImmutables::Dst(immutables.clone()));
+        vec![
+            &s.env,
+            call(s, &s.dst_factory, "create_dst_escrow", vec![&s.env, immutables.into_val(&s.env)]),
+            transfer(s, &immutables.token, &to, immutables.amount),
+        ]
+    }
+
+    #[test]
+    fn test_escrow_addresses_match_factories() {
+        let s = setup();
+
+        let src = src_immutables(&s, &s.account);
+        let val: Val = src.clone().into_val(&s.env);
+        let factory_src = escrow_src_factory::Immutables::try_from_val(&s.env, &val).unwrap();
+        assert_eq!(
+            escrow(&s, &EscrowImmutables::Src(src)),
+            EscrowSrcFactoryClient::new(&s.env, &s.src_factory).compute_escrow_address(&factory_src)
+        );
+
+        let dst = dst_immutables(&s, &s.account, 1);
+        let val: Val = dst.clone().into_val(&s.env);
+        let factory_dst = escrow_dst_factory::Immutables::try_from_val(&s.env, &val).unwrap();
+        assert_eq!(
+            escrow(&s, &EscrowImmutables::Dst(dst)),
+            EscrowDstFactoryClient::new(&s.env, &s.dst_factory).compute_escrow_address(&factory_dst)
+        );
+    }
+
+    #[test]
+    fn test_dst_fill_within_daily_limit() {
+        let s = setup();
+        let first = dst_immutables(&s, &s.account, 1);
+        let second = dst_immutables(&s, &s.account, 2);
+
+        let signature = proofs(&s, &[EscrowImmutables::Dst(first.clone())]);
+        assert_eq!(check_auth(&s, signature, dst_fill(&s, &first)), Ok(()));
+        assert_eq!(s.client.get_spent_today(&s.token), 1000);
+
+        // 2000 in a day is over the 1500 limit
+        let signature = proofs(&s, &[EscrowImmutables::Dst(second.clone())]);
+        assert_eq!(check_auth(&s, signature.clone(), dst_fill(&s, &second)), Err(Ok(Error::DailyLimitExceeded)));
+
+        // The limit starts over the next day
+        s.env.ledger().with_mut(|li| li.timestamp += DAY_IN_SECONDS);
+        assert_eq!(s.client.get_spent_today(&s.token), 0);
+        assert_eq!(check_auth(&s, signature, dst_fill(&s, &second)), Ok(()));
+
+        // Tokens without a limit cannot be moved at all
+        let mut other = dst_immutables(&s, &s.account, 3);
+        other.token = Address::generate(&s.env);
+        let signature = proofs(&s, &[EscrowImmutables::Dst(other.clone())]);
+        assert_eq!(check_auth(&s, signature.clone(), dst_fill(&s, &other)), Err(Ok(Error::DailyLimitExceeded)));
+
+        // Until the admin gives them one
+        s.client.set_daily_limit(&other.token, &1000);
+        assert_eq!(
+            account_events(&s),
+            vec![&s.env, ((symbol_short!("limit"), other.token.clone()).into_val(&s.env), 1000i128.into_val(&s.env))]
+        );
+        assert_eq!(check_auth(&s, signature, dst_fill(&s, &other)), Ok(()));
+    }
+
+    #[test]
+    fn test_transfers_only_to_own_escrows() {
+        let s = setup();
+        let immutables = dst_immutables(&s, &s.account, 1);
+        let stranger = Address::generate(&s.env);
+
+        // A plain transfer out of the treasury
+        let contexts = vec![&s.env, transfer(&s, &s.token, &stranger, 10)];
+        assert_eq!(check_auth(&s, proofs(&s, &[]), contexts), Err(Ok(Error::AuthNotAllowed)));
+
+        // Immutables that do not match the escrow being paid
+        let signature = proofs(&s, &[EscrowImmutables::Dst(immutables.clone())]);
+        let contexts = vec![&s.env, transfer(&s, &s.token, &stranger, 10)];
+        assert_eq!(check_auth(&s, signature, contexts), Err(Ok(Error::AuthNotAllowed)));
+
+        // Funding an escrow someone else is the taker of
+        let theirs = dst_immutables(&s, &stranger, 1);
+        let signature = proofs(&s, &[EscrowImmutables::Dst(theirs.clone())]);
+        let contexts = vec![&s.env, transfer(&s, &s.token, &escrow(&s, &EscrowImmutables::Dst(theirs)), 10)];
+        assert_eq!(check_auth(&s, signature, contexts), Err(Ok(Error::AuthNotAllowed)));
+
+        // Nothing was counted against the limit
+        assert_eq!(s.client.get_spent_today(&s.token), 0);
+    }
+
+    #[test]
+    fn test_escrow_calls() {
+        let s = setup();
+        let src = EscrowImmutables::Src(src_immutables(&s, &s.account));
+        let src_escrow = escrow(&s, &src);
+        let secret = BytesN::<32>::random(&s.env);
+
+        let contexts = vec![&s.env, call(&s, &src_escrow, "withdraw", vec![&s.env, secret.into_val(&s.env)])];
+        assert_eq!(check_auth(&s, proofs(&s, core::slice::from_ref(&src)), contexts.clone()), Ok(()));
+
+        // Escrows of other resolvers can be publicly cancelled for their safety deposit
+        let theirs = EscrowImmutables::Src(src_immutables(&s, &Address::generate(&s.env)));
+        let their_escrow = escrow(&s, &theirs);
+        let public_cancel = vec![&s.env, call(&s, &their_escrow, "public_cancel", vec![&s.env, s.account.into_val(&s.env)])];
+        assert_eq!(check_auth(&s, proofs(&s, &[theirs]), public_cancel), Ok(()));
+
+        // Unproven escrows are unknown contracts
+        assert_eq!(check_auth(&s, proofs(&s, &[]), contexts), Err(Ok(Error::AuthNotAllowed)));
+
+        // Withdrawing to an arbitrary target
+        let wdrawto = vec![&s.env, call(&s, &src_escrow, "wdrawto", vec![
+            &s.env,
+            secret.into_val(&s.env),
+            Address::generate(&s.env).into_val(&s.env),
+        ])];
+        assert_eq!(check_auth(&s, proofs(&s, &[src]), wdrawto), Err(Ok(Error::AuthNotAllowed)));
+
+        // The account's own admin functions are for the admin
+        let contexts = vec![&s.env, call(&s, &s.account, "set_daily_limit", vec![
+            &s.env,
+            s.token.into_val(&s.env),
+            i128::MAX.into_val(&s.env),
+        ])];
+        assert_eq!(check_auth(&s, proofs(&s, &[]), contexts), Err(Ok(Error::AuthNotAllowed)));
+    }
+
+    #[test]
+    fn test_rotated_signer() {
+        let s = setup();
+        let immutables = dst_immutables(&s, &s.account, 1);
+        let payload = BytesN::random(&s.env);
+        let escrows = vec![&s.env, EscrowImmutables::Dst(immutables.clone())];
+
+        let new_key = SigningKey::from_bytes(&[8u8; 32]);
+        let signer = BytesN::from_array(&s.env, &new_key.verifying_key().to_bytes());
+        s.client.set_signer(&signer);
+        assert_eq!(
+            account_events(&s),
+            vec![&s.env, ((symbol_short!("signer"),).into_val(&s.env), signer.into_val(&s.env))]
+        );
+        assert_eq!(s.client.get_signer(), signer);
+
+        // The leaked key no longer signs for the account
+        let old = sign(&s, &s.key, &payload, escrows.clone());
+        let result = s.env.try_invoke_contract_check_auth::<Error>(
+            &s.account, &payload, old.into_val(&s.env), &dst_fill(&s, &immutables),
+        );
+        assert!(result.is_err());
+
+        let new = sign(&s, &new_key, &payload, escrows);
+        let result = s.env.try_invoke_contract_check_auth::<Error>(
+            &s.account, &payload, new.into_val(&s.env), &dst_fill(&s, &immutables),
+        );
+        assert_eq!(result, Ok(()));
+    }
+}
@@ -0,0 +1,572 @@
+#![no_std]
+use soroban_sdk::{
+    auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation},
+    contract, contractclient, contractimpl, contracttype,
+    Address, BytesN, Env, IntoVal, Symbol, Val, symbol_short,
+    token, vec, Vec
+};
+
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+/// Size of a fee leg, either fixed or in basis points of the escrowed amount (same as the escrow contracts)
+#[contracttype]
+#[derive(Clone)]
+pub enum FeeAmount {
+    Fixed(i128),
+    Bps(u32),
+}
+
+/// Part of the withdrawn tokens paid to a protocol or integrator (same as the escrow contracts)
+#[contracttype]
+#[derive(Clone)]
+pub struct FeeLeg {
+    pub recipient: Address,
+    pub amount: FeeAmount,
+}
+
+/// Clock the timelocks of an escrow count in (same as EscrowSrcFactory)
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeBase {
+    Timestamp,                          // Seconds of ledger close time
+    Sequence,                           // Ledger sequence numbers
+}
+
+/// Immutable parameters for a source escrow (same as EscrowSrcFactory)
+#[contracttype]
+#[derive(Clone)]
+pub struct SrcImmutables {
+    pub order_hash: BytesN<32>,
+    pub hashlock: BytesN<32>,
+    pub maker: Address,
+    pub taker: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub safety_deposit: i128,
+    pub src_chain_id: u64,              // Chain the maker's tokens are locked on
+    pub dst_chain_id: u64,              // Chain the taker's tokens are paid out on
+    pub time_base: TimeBase,            // Clock the timelocks and deployed_at count in
+    pub deployed_at: u64,
+    // Timelock durations from deployment, in seconds or ledgers per time_base
+    pub src_withdrawal_start: u32,      // When taker can withdraw
+    pub src_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub src_cancellation_start: u32,     // When taker can cancel
+    pub src_public_cancellation_start: u32, // When anyone can cancel
+    pub dst_withdrawal_start: u32,      // When taker can withdraw
+    pub dst_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub dst_cancellation_start: u32,     // When taker can cancel
+    pub allow_clawback: bool,           // Order accepts tokens the issuer can claw back
+    pub fees: Vec<FeeLeg>,              // Paid out of the amount on withdrawal, at most MAX_FEE_LEGS
+}
+
+/// Immutable parameters for a destination escrow (same as EscrowDstFactory)
+#[contracttype]
+#[derive(Clone)]
+pub struct DstImmutables {
+    pub order_hash: BytesN<32>,
+    pub hashlock: BytesN<32>,
+    pub maker: Address,
+    pub receiver: Option<Address>,      // Paid on withdrawal instead of the maker, if set
+    pub taker: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub safety_deposit: i128,
+    pub src_chain_id: u64,              // Chain the maker's tokens are locked on
+    pub dst_chain_id: u64,              // Chain the taker's tokens are paid out on
+    pub time_base: TimeBase,            // Clock the timelocks and deployed_at count in
+    pub deployed_at: u64,
+    // Timelock durations from deployment, in seconds or ledgers per time_base
+    pub src_withdrawal_start: u32,      // When taker can withdraw
+    pub src_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub src_cancellation_start: u32,     // When taker can cancel
+    pub src_public_cancellation_start: u32, // When anyone can cancel
+    pub dst_withdrawal_start: u32,      // When taker can withdraw
+    pub dst_public_withdrawal_start: u32, // When anyone can withdraw for taker
+    pub dst_cancellation_start: u32,     // When taker can cancel
+    pub allow_clawback: bool,           // Order accepts tokens the issuer can claw back
+    pub fees: Vec<FeeLeg>,              // Paid out of the amount on withdrawal, at most MAX_FEE_LEGS
+    // Surplus sharing, set by the factory from the order's parameters
+    pub estimated_amount: i128,         // Taking amount the order expected
+    pub surplus_percentage: u32,        // Share of the excess over the estimate paid to the protocol, 0 to 100
+    pub protocol_fee_receiver: Option<Address>, // Receives the surplus share, if any
+}
+
+/// Contract call made on the resolver's behalf by `arbitrary_calls`
+#[contracttype]
+#[derive(Clone)]
+pub struct Call {
+    pub contract: Address,
+    pub function: Symbol,
+    pub args: Vec<Val>,
+}
+
+/// Interface of the EscrowSrcFactory
+#[contractclient(name = "EscrowSrcFactoryClient")]
+pub trait EscrowSrcFactoryInterface {
+    fn createsrc(env: Env, src_immutables: SrcImmutables) -> Result<Address, Error>;
+    fn compute_escrow_address(env: Env, immutables: SrcImmutables) -> Address;
+}
+
+/// Interface of the EscrowDstFactory
+#[contractclient(name = "EscrowDstFactoryClient")]
+pub trait EscrowDstFactoryInterface {
+    fn create_dst_escrow(
+        env: Env,
+        dst_immutables: DstImmutables,
+        src_cancellation_timestamp: u64,
+        estimated_taking_amount: i128,
+        surplus_percentage: u32,
+    ) -> Result<Address, Error>;
+    fn compute_escrow_address(env: Env, immutables: DstImmutables) -> Address;
+    fn get_protocol_fee_receiver(env: Env) -> Option<Address>;
+}
+
+/// Taker-side entry points shared by EscrowSrc and EscrowDst
+#[contractclient(name = "EscrowClient")]
+pub trait EscrowInterface {
+    fn withdraw(env: Env, secret: BytesN<32>) -> Result<(), Error>;
+    fn cancel(env: Env) -> Result<(), Error>;
+}
+
+/// Resolver acting as the taker of its escrows, so one owner key can run whole fills
+#[contract]
+pub struct Resolver;
+
+#[contractimpl]
+impl Resolver {
+    /// Configure the resolver with its owner, the escrow factories and the native XLM asset contract
+    pub fn __constructor(env: Env, owner: Address, src_factory: Address, dst_factory: Address, native_token: Address) {
+        env.storage().instance().set(&symbol_short!("owner"), &owner);
+        env.storage().instance().set(&symbol_short!("srcfact"), &src_factory);
+        env.storage().instance().set(&symbol_short!("dstfact"), &dst_factory);
+        env.storage().instance().set(&symbol_short!("native"), &native_token);
+    }
+
+    /// Send the safety deposit to the source escrow address and create the escrow (owner only)
+    ///
+    /// The maker still has to authorize `createsrc`, which pulls the tokens from them.
+    pub fn deploy_src(env: Env, immutables: SrcImmutables) -> Result<Address, Error> {
+        Self::require_owner(&env);
+        Self::require_taker(&env, &immutables.taker)?;
+
+        let factory = EscrowSrcFactoryClient::new(&env, &Self::src_factory(&env));
+        let escrow_address = factory.compute_escrow_address(&immutables);
+        Self::send_safety_deposit(&env, &escrow_address, immutables.safety_deposit)?;
+
+        let escrow = match factory.try_createsrc(&immutables) {
+            Ok(Ok(escrow)) => escrow,
+            Err(Ok(error)) => return Err(error),
+            _ => return Err(Error::EscrowCreationFailed),
+        };
+
+        env.events().publish((symbol_short!("deployed"), symbol_short!("src")), escrow.clone());
+
+        Ok(escrow)
+    }
+
+    /// Send the safety deposit to the destination escrow address and create the escrow
+    /// with the resolver's own tokens (owner only)
+    pub fn deploy_dst(
+        env: Env,
+        immutables: DstImmutables,
+        src_cancellation_timestamp: u64,
+        estimated_taking_amount: i128,
+        surplus_percentage: u32,
+    ) -> Result<Address, Error> {
+        Self::require_owner(&env);
+        Self::require_taker(&env, &immutables.taker)?;
+
+        let factory = EscrowDstFactoryClient::new(&env, &Self::dst_factory(&env));
+
+        // The escrow address commits to the surplus share the factory will record
+        let mut recorded = immutables.clone();
+        recorded.estimated_amount = estimated_taking_amount;
+        recorded.surplus_percentage = surplus_percentage;
+        recorded.protocol_fee_receiver = None;
+        if surplus_percentage > 0 {
+            recorded.protocol_fee_receiver = factory.get_protocol_fee_receiver();
+        }
+        let escrow_address = factory.compute_escrow_address(&recorded);
+        Self::send_safety_deposit(&env, &escrow_address, immutables.safety_deposit)?;
+
+        // Let the factory pull the tokens from the resolver into the escrow
+        env.authorize_as_current_contract(vec![
+            &env,
+            InvokerContractAuthEntry::Contract(SubContractInvocation {
+                context: ContractContext {
+                    contract: immutables.token.clone(),
+                    fn_name: Symbol::new(&env, "transfer"),
+                    args: (env.current_contract_address(), escrow_address.clone(), immutables.amount).into_val(&env),
+                },
+                sub_invocations: vec![&env],
+            }),
+        ]);
+
+        let escrow = match factory.try_create_dst_escrow(
+            &immutables,
+            &src_cancellation_timestamp,
+            &estimated_taking_amount,
+            &surplus_percentage,
+        ) {
+            Ok(Ok(escrow)) => escrow,
+            Err(Ok(error)) => return Err(error),
+            _ => return Err(Error::EscrowCreationFailed),
+        };
+
+        env.events().publish((symbol_short!("deployed"), symbol_short!("dst")), escrow.clone());
+
+        Ok(escrow)
+    }
+
+    /// Withdraw from a source or destination escrow the resolver is the taker of (owner only)
+    pub fn withdraw(env: Env, escrow: Address, secret: BytesN<32>) -> Result<(), Error> {
+        Self::require_owner(&env);
+
+        match EscrowClient::new(&env, &escrow).try_withdraw(&secret) {
+            Ok(Ok(())) => Ok(()),
+            Err(Ok(error)) => Err(error),
+            _ => Err(Error::TransferFailed),
+        }
+    }
+
+    /// Cancel a source or destination escrow the resolver is the taker of (owner only)
+    pub fn cancel(env: Env, escrow: Address) -> Result<(), Error> {
+        Self::require_owner(&env);
+
+        match EscrowClient::new(&env, &escrow).try_cancel() {
+            Ok(Ok(())) => Ok(()),
+            Err(Ok(error)) => Err(error),
+            _ => Err(Error::TransferFailed),
+        }
+    }
+
+    /// Make a batch of calls as the resolver, returning their results (owner only)
+    ///
+    /// Any failing call reverts the whole batch.
+    pub fn arbitrary_calls(env: Env, calls: Vec<Call>) -> Vec<Val> {
+        Self::require_owner(&env);
+
+        let mut results = Vec::new(&env);
+        for call in calls.iter() {
+            let result: Val = env.invoke_contract(&call.contract, &call.function, call.args);
+            results.push_back(result);
+        }
+        results
+    }
+
+    /// Get the owner
+    pub fn get_owner(env: Env) -> Address {
+        env.storage().instance().get(&symbol_short!("owner")).unwrap()
+    }
+
+    // Helper functions
+
+    fn require_owner(env: &Env) {
+        Self::get_owner(env.clone()).require_auth();
+    }
+
+    /// Escrows deployed through the resolver must name it as their taker
+    fn require_taker(env: &Env, taker: &Address) -> Result<(), Error> {
+        if *taker != env.current_contract_address() {
+            return Err(Error::InvalidAddress);
+        }
+        Ok(())
+    }
+
+    fn src_factory(env: &Env) -> Address {
+        env.storage().instance().get(&symbol_short!("srcfact")).unwrap()
+    }
+
+    fn dst_factory(env: &Env) -> Address {
+        env.storage().instance().get(&symbol_short!("dstfact")).unwrap()
+    }
+
+    /// Pay the safety deposit (native XLM) from the resolver's balance to the escrow address
+    fn send_safety_deposit(env: &Env, escrow_address: &Address, amount: i128) -> Result<(), Error> {
+        if amount == 0 {
+            return Ok(());
+        }
+        let native_token: Address = env.storage().instance().get(&symbol_short!("native")).unwrap();
+        match token::Client::new(env, &native_token).try_transfer(&env.current_contract_address(), escrow_address, &amount) {
+            Ok(Ok(())) => Ok(()),
+            _ => Err(Error::InsufficientBalance),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use escrow_dst_factory::EscrowDstFactory;
+    use escrow_src_factory::EscrowSrcFactory;
+    use soroban_sdk::{
+        Address, Bytes, BytesN, Env, IntoVal, Symbol,
+        testutils::{Address as _, Events as _, Ledger as _, MockAuth, MockAuthInvoke},
+        token::{StellarAssetClient, TokenClient},
+    };
+
+    /// Escrow wasms the factories deploy (build them with `make build` first)
+    mod escrow_src_wasm {
+        soroban_sdk::contractimport!(file = "../../target/wasm32v1-none/release/escrow_src.wasm");
+    }
+    mod escrow_dst_wasm {
+        soroban_sdk::contractimport!(file = "../../target/wasm32v1-none/release/escrow_dst.wasm");
+    }
+
+    struct Setup<'a> {
+        env: Env,
+        contract_id: Address,
+        client: ResolverClient<'a>,
+        owner: Address,
+        native_token: Address,
+        src_factory: Address,
+        dst_factory: Address,
+    }
+
+    /// Resolver holding 1000 XLM for safety deposits, wired to both factories
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let src_wasm_hash = env.deployer().upload_contract_wasm(escrow_src_wasm::WASM);
+        let dst_wasm_hash = env.deployer().upload_contract_wasm(escrow_dst_wasm::WASM);
+        let native_token = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+
+        let src_factory = env.register(EscrowSrcFactory, (admin.clone(), src_wasm_hash, native_token.clone(), 1000u64));
+        let dst_factory = env.register(EscrowDstFactory, (admin, dst_wasm_hash, native_token.clone(), 1000u64));
+        let contract_id = env.register(
+            Resolver,
+            (owner.clone(), src_factory.clone(), dst_factory.clone(), native_token.clone()),
+        );
+        let client = ResolverClient::new(&env, &contract_id);
+
+        // The maker authorizes `createsrc` below the resolver's call
+        env.mock_all_auths_allowing_non_root_auth();
+        StellarAssetClient::new(&env, &native_token).mint(&contract_id, &1000);
+
+        Setup { env, contract_id, client, owner, native_token, src_factory, dst_factory }
+    }
+
+    fn hashlock(env: &Env, secret: &BytesN<32>) -> BytesN<32> {
+        env.crypto().sha256(&Bytes::from_slice(env, &secret.to_array())).into()
+    }
+
+    fn src_secret(env: &Env) -> BytesN<32> {
+        BytesN::from_array(env, &[2u8; 32])
+    }
+
+    fn src_immutables(s: &Setup, token: &Address) -> SrcImmutables {
+        SrcImmutables {
+            order_hash: BytesN::from_array(&s.env, &[1u8; 32]),
+            hashlock: hashlock(&s.env, &src_secret(&s.env)),
+            maker: Address::generate(&s.env),
+            taker: s.contract_id.clone(),
+            token: token.clone(),
+            amount: 1000,
+            safety_deposit: 100,
+            src_chain_id: 1000,
+            dst_chain_id: 1,
+            time_base: TimeBase::Timestamp,
+            deployed_at: 0,
+            src_withdrawal_start: 60,
+            src_public_withdrawal_start: 120,
+            src_cancellation_start: 300,
+            src_public_cancellation_start: 600,
+            dst_withdrawal_start: 60,
+            dst_public_withdrawal_start: 120,
+            dst_cancellation_start: 300,
+            allow_clawback: false,
+            fees: Vec::new(&s.env),
+        }
+    }
+
+    fn dst_immutables(s: &Setup, token: &Address, secret: &BytesN<32>) -> DstImmutables {
+        DstImmutables {
+            order_hash: BytesN::from_array(&s.env, &[1u8; 32]),
+            hashlock: hashlock(&s.env, secret),
+            maker: Address::generate(&s.env),
+            receiver: None,
+            taker: s.contract_id.clone(),
+            token: token.clone(),
+            amount: 1000,
+            safety_deposit: 100,
+            src_chain_id: 1,
+            dst_chain_id: 1000,
+            time_base: TimeBase::Timestamp,
+            deployed_at: 0,
+            src_withdrawal_start: 60,
+            src_public_withdrawal_start: 120,
+            src_cancellation_start: 300,
+            src_public_cancellation_start: 600,
+            dst_withdrawal_start: 60,
+            dst_public_withdrawal_start: 120,
+            dst_cancellation_start: 300,
+            allow_clawback: false,
+            fees: Vec::new(&s.env),
+            estimated_amount: 0,
+            surplus_percentage: 0,
+            protocol_fee_receiver: None,
+        }
+    }
+
+    fn new_token(s: &Setup) -> Address {
+        s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address()
+    }
+
+    fn balance(s: &Setup, token: &Address, id: &Address) -> i128 {
+        TokenClient::new(&s.env, token).balance(id)
+    }
+
+    fn set_time(env: &Env, timestamp: u64) {
+        env.ledger().with_mut(|li| {
+            li.timestamp = timestamp;
+        });
+    }
+
+    /// Topics and data of the events the resolver published in the last invocation
+    fn resolver_events(s: &Setup) -> Vec<(Vec<Val>, Val)> {
+        let mut events = Vec::new(&s.env);
+        for (contract, topics, data) in s.env.events().all().iter() {
+            if contract == s.contract_id {
+                events.push_back((topics, data));
+            }
+        }
+        events
+    }
+
+    /// Deploy a source escrow for a maker holding the tokens
+    fn deploy_src(s: &Setup) -> (SrcImmutables, Address) {
+        let token = new_token(s);
+        let immutables = src_immutables(s, &token);
+        StellarAssetClient::new(&s.env, &token).mint(&immutables.maker, &immutables.amount);
+
+        let factory = EscrowSrcFactoryClient::new(&s.env, &s.src_factory);
+        let escrow_address = factory.compute_escrow_address(&immutables);
+
+        assert_eq!(s.client.deploy_src(&immutables), escrow_address);
+        let event = ((symbol_short!("deployed"), symbol_short!("src")).into_val(&s.env), escrow_address.into_val(&s.env));
+        assert_eq!(resolver_events(s), vec![&s.env, event]);
+        (immutables, escrow_address)
+    }
+
+    #[test]
+    fn test_deploy_src() {
+        let s = setup();
+        let (immutables, escrow_address) = deploy_src(&s);
+
+        // The maker's tokens and the resolver's safety deposit are in the escrow
+        assert_eq!(balance(&s, &immutables.token, &escrow_address), 1000);
+        assert_eq!(balance(&s, &s.native_token, &escrow_address), 100);
+        assert_eq!(balance(&s, &s.native_token, &s.contract_id), 900);
+
+        let escrow = escrow_src::EscrowSrcClient::new(&s.env, &escrow_address);
+        assert_eq!(escrow.get_state(), escrow_src::State::Active);
+    }
+
+    #[test]
+    fn test_withdraw_and_cancel_src() {
+        let s = setup();
+        let (immutables, escrow_address) = deploy_src(&s);
+
+        set_time(&s.env, 100);
+        s.client.withdraw(&escrow_address, &src_secret(&s.env));
+
+        // The resolver is the taker and collects the tokens and its safety deposit
+        assert_eq!(balance(&s, &immutables.token, &s.contract_id), 1000);
+        assert_eq!(balance(&s, &s.native_token, &s.contract_id), 1000);
+
+        // Escrow errors are passed through
+        assert_eq!(s.client.try_cancel(&escrow_address), Err(Ok(Error::AlreadyWithdrawn)));
+    }
+
+    #[test]
+    fn test_deploy_dst_with_owner_auth_only() {
+        let s = setup();
+        let secret = BytesN::from_array(&s.env, &[7u8; 32]);
+        let token = new_token(&s);
+        let immutables = dst_immutables(&s, &token, &secret);
+        StellarAssetClient::new(&s.env, &token).mint(&s.contract_id, &immutables.amount);
+
+        let factory = EscrowDstFactoryClient::new(&s.env, &s.dst_factory);
+        let escrow_address = factory.compute_escrow_address(&immutables);
+
+        // Only the owner signs; the resolver authorizes the token pull itself
+        let src_cancellation_time = 10_000u64;
+        s.env.mock_auths(&[MockAuth {
+            address: &s.owner,
+            invoke: &MockAuthInvoke {
+                contract: &s.contract_id,
+                fn_name: "deploy_dst",
+                args: (immutables.clone(), src_cancellation_time, 0i128, 0u32).into_val(&s.env),
+                sub_invokes: &[],
+            },
+        }]);
+        assert_eq!(s.client.deploy_dst(&immutables, &src_cancellation_time, &0, &0), escrow_address);
+        let event = ((symbol_short!("deployed"), symbol_short!("dst")).into_val(&s.env), escrow_address.into_val(&s.env));
+        assert_eq!(resolver_events(&s), vec![&s.env, event]);
+
+        assert_eq!(balance(&s, &token, &escrow_address), 1000);
+        assert_eq!(balance(&s, &token, &s.contract_id), 0);
+        assert_eq!(balance(&s, &s.native_token, &escrow_address), 100);
+
+        // Reveal the secret on the destination chain
+        s.env.mock_all_auths_allowing_non_root_auth();
+        set_time(&s.env, 100);
+        s.client.withdraw(&escrow_address, &secret);
+        assert_eq!(balance(&s, &token, &immutables.maker), 1000);
+        assert_eq!(balance(&s, &s.native_token, &s.contract_id), 1000);
+    }
+
+    #[test]
+    fn test_deploy_requires_resolver_taker() {
+        let s = setup();
+        let token = new_token(&s);
+
+        let mut immutables = src_immutables(&s, &token);
+        immutables.taker = Address::generate(&s.env);
+        assert_eq!(s.client.try_deploy_src(&immutables), Err(Ok(Error::InvalidAddress)));
+
+        let mut immutables = dst_immutables(&s, &token, &BytesN::from_array(&s.env, &[7u8; 32]));
+        immutables.taker = Address::generate(&s.env);
+        assert_eq!(s.client.try_deploy_dst(&immutables, &10_000, &0, &0), Err(Ok(Error::InvalidAddress)));
+    }
+
+    #[test]
+    fn test_arbitrary_calls() {
+        let s = setup();
+        let recipient = Address::generate(&s.env);
+
+        let transfer = Call {
+            contract: s.native_token.clone(),
+            function: Symbol::new(&s.env, "transfer"),
+            args: (s.contract_id.clone(), recipient.clone(), 250i128).into_val(&s.env),
+        };
+        let balance_of = Call {
+            contract: s.native_token.clone(),
+            function: Symbol::new(&s.env, "balance"),
+            args: (recipient.clone(),).into_val(&s.env),
+        };
+
+        let results = s.client.arbitrary_calls(&vec![&s.env, transfer.clone(), transfer, balance_of]);
+        assert_eq!(results.len(), 3);
+        let recipient_balance: i128 = results.get(2).unwrap().into_val(&s.env);
+        assert_eq!(recipient_balance, 500);
+        assert_eq!(balance(&s, &s.native_token, &s.contract_id), 500);
+    }
+
+    #[test]
+    fn test_owner_only() {
+        let s = setup();
+        let (immutables, escrow_address) = deploy_src(&s);
+        set_time(&s.env, 100);
+
+        s.env.set_auths(&[]);
+        assert!(s.client.try_withdraw(&escrow_address, &src_secret(&s.env)).is_err());
+        assert!(s.client.try_cancel(&escrow_address).is_err());
+        assert!(s.client.try_arbitrary_calls(&Vec::new(&s.env)).is_err());
+        assert!(s.client.try_deploy_src(&immutables).is_err());
+        assert_eq!(s.client.get_owner(), s.owner);
+    }
+}
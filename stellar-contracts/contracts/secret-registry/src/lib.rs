@@ -0,0 +1,116 @@
+#![no_std]
+use soroban_sdk::{
+    contract, contractimpl,
+    Bytes, BytesN, Env, Symbol, symbol_short
+};
+
+pub use escrow_errors::Error;
+escrow_errors::export_error_spec!();
+
+/// Approximate number of ledgers closed per day
+const DAY_IN_LEDGERS: u32 = 17_280;
+
+/// Revealed secrets are extended to live this many ledgers ahead...
+const SECRET_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+
+/// ...whenever they are touched with less than this left
+const SECRET_LIFETIME_THRESHOLD: u32 = SECRET_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+/// Public record of secrets revealed for a hashlock, so escrows sharing it can be withdrawn
+#[contract]
+pub struct SecretRegistry;
+
+#[contractimpl]
+impl SecretRegistry {
+    /// Publish the preimage of a hashlock (anyone can call)
+    ///
+    /// The secret must hash to the hashlock with SHA-256, as the escrows check it.
+    /// Publishing a secret that is already registered only extends its TTL.
+    pub fn register(env: Env, hashlock: BytesN<32>, secret: BytesN<32>) -> Result<(), Error> {
+        let computed_hash: BytesN<32> = env.crypto().sha256(&Bytes::from_slice(&env, &secret.to_array())).into();
+        if computed_hash != hashlock {
+            return Err(Error::InvalidSecret);
+        }
+
+        let key = (symbol_short!("secret"), hashlock.clone());
+        if !env.storage().persistent().has(&key) {
+            env.storage().persistent().set(&key, &secret);
+            env.events().publish((Symbol::new(&env, "registered"), hashlock), secret);
+        }
+        env.storage().persistent().extend_ttl(&key, SECRET_LIFETIME_THRESHOLD, SECRET_BUMP_AMOUNT);
+
+        Ok(())
+    }
+
+    /// Get the secret revealed for a hashlock, if any
+    pub fn get_secret(env: Env, hashlock: BytesN<32>) -> Option<BytesN<32>> {
+        env.storage().persistent().get(&(symbol_short!("secret"), hashlock))
+    }
+
+    /// Check whether the secret for a hashlock has been revealed
+    pub fn is_revealed(env: Env, hashlock: BytesN<32>) -> bool {
+        env.storage().persistent().has(&(symbol_short!("secret"), hashlock))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::{
+        vec, Env, IntoVal,
+        testutils::{storage::Persistent, Events as _},
+    };
+
+    fn hashlock(env: &Env, secret: &BytesN<32>) -> BytesN<32> {
+        env.crypto().sha256(&Bytes::from_slice(env, &secret.to_array())).into()
+    }
+
+    #[test]
+    fn test_register() {
+        let env = Env::default();
+        let contract_id = env.register(SecretRegistry, ());
+        let client = SecretRegistryClient::new(&env, &contract_id);
+
+        let secret = BytesN::from_array(&env, &[2u8; 32]);
+        let hashlock = hashlock(&env, &secret);
+        assert!(!client.is_revealed(&hashlock));
+        assert_eq!(client.get_secret(&hashlock), None);
+
+        client.register(&hashlock, &secret);
+        assert_eq!(
+            env.events().all(),
+            vec![
+                &env,
+                (
+                    contract_id.clone(),
+                    (Symbol::new(&env, "registered"), hashlock.clone()).into_val(&env),
+                    secret.into_val(&env)
+                )
+            ]
+        );
+        assert!(client.is_revealed(&hashlock));
+        assert_eq!(client.get_secret(&hashlock), Some(secret.clone()));
+
+        // Publishing again is harmless, and announces nothing new
+        client.register(&hashlock, &secret);
+        assert!(env.events().all().is_empty());
+
+        let ttl = env.as_contract(&contract_id, || {
+            env.storage().persistent().get_ttl(&(symbol_short!("secret"), hashlock.clone()))
+        });
+        assert_eq!(ttl, SECRET_BUMP_AMOUNT);
+    }
+
+    #[test]
+    fn test_register_invalid_secret() {
+        let env = Env::default();
+        let client = SecretRegistryClient::new(&env, &env.register(SecretRegistry, ()));
+
+        let secret = BytesN::from_array(&env, &[2u8; 32]);
+        let hashlock = hashlock(&env, &secret);
+
+        // The hashlock itself is not its own preimage
+        assert_eq!(client.try_register(&hashlock, &hashlock), Err(Ok(Error::InvalidSecret)));
+        assert!(!client.is_revealed(&hashlock));
+    }
+}